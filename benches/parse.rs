@@ -0,0 +1,63 @@
+//! Benchmarks for BudouX Rust Wrapper
+
+use budoux_rust_wrapper::{models, FeatureGroup, Model, Parser};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const SENTENCE: &str = "私は遅刻魔で、待ち合わせにいつも遅刻してしまいます。\
+    メールで待ち合わせ相手に一言、「ごめんね」と謝ればどうにかなると思っていました。\
+    海外ではケータイを持っていない。";
+
+fn bench_parse(c: &mut Criterion) {
+    let parser = budoux_rust_wrapper::load_default_japanese_parser();
+    let long = SENTENCE.repeat(20);
+
+    c.bench_function("parse_sentence", |b| {
+        b.iter(|| parser.parse(black_box(SENTENCE)))
+    });
+    c.bench_function("parse_long", |b| b.iter(|| parser.parse(black_box(&long))));
+    c.bench_function("parse_repeated", |b| {
+        b.iter(|| parser.parse(black_box("すごーーーーーーい！！！。。。ーーーー")))
+    });
+    c.bench_function("boundary_scores_long", |b| {
+        b.iter(|| parser.boundary_scores(black_box(&long)))
+    });
+}
+
+/// Keep the `per_group` highest-scoring entries of each group of the ja model
+fn small_model(per_group: usize) -> Model {
+    let mut model = models()["ja"].clone();
+    for group in FeatureGroup::ALL {
+        let feature = match group {
+            FeatureGroup::UW1 => &mut model.uw1,
+            FeatureGroup::UW2 => &mut model.uw2,
+            FeatureGroup::UW3 => &mut model.uw3,
+            FeatureGroup::UW4 => &mut model.uw4,
+            FeatureGroup::UW5 => &mut model.uw5,
+            FeatureGroup::UW6 => &mut model.uw6,
+            FeatureGroup::BW1 => &mut model.bw1,
+            FeatureGroup::BW2 => &mut model.bw2,
+            FeatureGroup::BW3 => &mut model.bw3,
+            FeatureGroup::TW1 => &mut model.tw1,
+            FeatureGroup::TW2 => &mut model.tw2,
+            FeatureGroup::TW3 => &mut model.tw3,
+            FeatureGroup::TW4 => &mut model.tw4,
+        };
+        let mut entries: Vec<_> = feature.drain().collect();
+        entries.sort_by_key(|(_, score)| -score.abs());
+        feature.extend(entries.into_iter().take(per_group));
+    }
+    model
+}
+
+fn bench_small_model(c: &mut Criterion) {
+    let long = SENTENCE.repeat(20);
+    for per_group in [8, 32, 128] {
+        let parser = Parser::new(small_model(per_group));
+        c.bench_function(&format!("parse_long_small_model_{}", per_group), |b| {
+            b.iter(|| parser.parse(black_box(&long)))
+        });
+    }
+}
+
+criterion_group!(benches, bench_parse, bench_small_model);
+criterion_main!(benches);
@@ -0,0 +1,19 @@
+//! Insert zero-width spaces between the chunks of a text file
+//!
+//! Usage: cargo run --example segment_file -- <input> <output>
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [input, output] = args.as_slice() else {
+        eprintln!("usage: segment_file <input> <output>");
+        std::process::exit(2);
+    };
+
+    let parser = budoux_rust_wrapper::load_default_japanese_parser();
+    if let Err(e) =
+        budoux_rust_wrapper::segment_file(&parser, input, output, budoux_rust_wrapper::ZWSP)
+    {
+        eprintln!("segment_file: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,340 @@
+//! HTML processing for inserting break opportunities into markup
+
+use crate::{Parser, ZWSP};
+
+/// Elements whose text is neither segmented nor modified
+const SKIP_ELEMENTS: &[&str] = &["code", "rp", "rt", "script", "style", "textarea"];
+
+/// Elements that do not interrupt the sentence around them
+const INLINE_ELEMENTS: &[&str] = &[
+    "a", "abbr", "b", "bdi", "bdo", "cite", "code", "dfn", "em", "font", "i", "kbd", "mark", "q",
+    "rb", "rp", "rt", "ruby", "s", "samp", "small", "span", "strong", "sub", "sup", "time", "u",
+    "var", "wbr",
+];
+
+/// Elements that never have content or a closing tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// A piece of the HTML source
+enum Token<'a> {
+    /// A tag, comment or doctype, copied to the output unchanged
+    Tag {
+        raw: &'a str,
+        name: String,
+        closing: bool,
+        // A self-closing tag or void element, which opens nothing
+        empty: bool,
+    },
+    /// Text between tags
+    Text(&'a str),
+}
+
+/// Text of consecutive inline content, segmented as one sentence
+#[derive(Default)]
+struct Run {
+    text: String,
+    // Token index and byte offset in that token of each character in `text`
+    positions: Vec<(usize, usize)>,
+    // Ruby element each character belongs to, if any
+    ruby: Vec<Option<usize>>,
+}
+
+impl Parser {
+    /// Insert zero-width spaces at break opportunities in the text of an HTML
+    /// fragment
+    ///
+    /// Tags, attributes and character references are preserved as written. Text
+    /// inside inline elements is segmented together with its surroundings,
+    /// while other elements start a new sentence. The contents of `code`,
+    /// `script`, `style`, `textarea`, `rt` and `rp` are left untouched and
+    /// excluded from scoring, and the base text of a `ruby` element is never
+    /// split. Apply `word-break: keep-all; overflow-wrap: anywhere;` to the
+    /// container so browsers only break at the inserted markers.
+    pub fn translate_html(&self, html: &str) -> String {
+        self.translate_html_within(html, None)
+    }
+
+    /// Insert zero-width spaces like [`Parser::translate_html`], but only in
+    /// text inside one of the elements named in `tags`
+    ///
+    /// Tag names are matched case-insensitively. Each listed element starts a
+    /// new sentence, and text outside all of them is left untouched and
+    /// excluded from scoring.
+    pub fn translate_html_in(&self, html: &str, tags: &[&str]) -> String {
+        self.translate_html_within(html, Some(tags))
+    }
+
+    // Helper method to translate the text inside `tags`, or all text when
+    // `tags` is `None`
+    fn translate_html_within(&self, html: &str, tags: Option<&[&str]>) -> String {
+        let tokens = tokenize(html);
+        let mut insertions = Vec::new();
+        let mut run = Run::default();
+        let mut skip_depth = 0usize;
+        let mut target_depth = 0usize;
+        let mut ruby_depth = 0usize;
+        let mut ruby_count = 0usize;
+
+        for (idx, token) in tokens.iter().enumerate() {
+            match token {
+                Token::Tag {
+                    name,
+                    closing,
+                    empty,
+                    ..
+                } => {
+                    let name = name.as_str();
+                    if SKIP_ELEMENTS.contains(&name) && !*empty {
+                        skip_depth = if *closing {
+                            skip_depth.saturating_sub(1)
+                        } else {
+                            skip_depth + 1
+                        };
+                    }
+                    if name == "ruby" && !*empty {
+                        if *closing {
+                            ruby_depth = ruby_depth.saturating_sub(1);
+                        } else {
+                            ruby_depth += 1;
+                            if ruby_depth == 1 {
+                                ruby_count += 1;
+                            }
+                        }
+                    }
+                    let is_target =
+                        tags.is_some_and(|tags| tags.iter().any(|t| t.eq_ignore_ascii_case(name)));
+                    if is_target && !*empty {
+                        target_depth = if *closing {
+                            target_depth.saturating_sub(1)
+                        } else {
+                            target_depth + 1
+                        };
+                    }
+                    if is_target || !INLINE_ELEMENTS.contains(&name) {
+                        self.collect_insertions(&std::mem::take(&mut run), &mut insertions);
+                    }
+                }
+                Token::Text(text) if skip_depth == 0 && (tags.is_none() || target_depth > 0) => {
+                    let ruby = (ruby_depth > 0).then_some(ruby_count);
+                    for (offset, c) in decode_text(text) {
+                        run.text.push(c);
+                        run.positions.push((idx, offset));
+                        run.ruby.push(ruby);
+                    }
+                }
+                Token::Text(_) => {}
+            }
+        }
+        self.collect_insertions(&run, &mut insertions);
+
+        let mut output = String::with_capacity(html.len() + insertions.len() * ZWSP.len());
+        let mut pending = insertions.into_iter().peekable();
+        for (idx, token) in tokens.iter().enumerate() {
+            match token {
+                Token::Tag { raw, .. } => output.push_str(raw),
+                Token::Text(text) => {
+                    let mut last = 0;
+                    while let Some(&(_, offset)) = pending.peek().filter(|(i, _)| *i == idx) {
+                        output.push_str(&text[last..offset]);
+                        output.push_str(ZWSP);
+                        last = offset;
+                        pending.next();
+                    }
+                    output.push_str(&text[last..]);
+                }
+            }
+        }
+        output
+    }
+
+    // Helper method to find the source positions that start a new chunk
+    fn collect_insertions(&self, run: &Run, insertions: &mut Vec<(usize, usize)>) {
+        let mask = self.break_mask(&run.text);
+        for (i, &starts) in mask.iter().enumerate().skip(1) {
+            let inside_ruby = run.ruby[i].is_some() && run.ruby[i] == run.ruby[i - 1];
+            if starts && !inside_ruby {
+                insertions.push(run.positions[i]);
+            }
+        }
+    }
+}
+
+/// Split HTML into tags and text
+fn tokenize(html: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+    while !rest.is_empty() {
+        let tag_start = match rest.find('<') {
+            Some(0) => 0,
+            Some(pos) => {
+                tokens.push(Token::Text(&rest[..pos]));
+                pos
+            }
+            None => {
+                tokens.push(Token::Text(rest));
+                break;
+            }
+        };
+        rest = &rest[tag_start..];
+
+        let end = if rest.starts_with("<!--") {
+            rest.find("-->").map_or(rest.len(), |pos| pos + 3)
+        } else {
+            tag_end(rest)
+        };
+        let raw = &rest[..end];
+        let closing = raw.starts_with("</");
+        let name = raw
+            .trim_start_matches("</")
+            .trim_start_matches('<')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        let empty = !closing && (raw.ends_with("/>") || VOID_ELEMENTS.contains(&name.as_str()));
+        tokens.push(Token::Tag {
+            raw,
+            name,
+            closing,
+            empty,
+        });
+        rest = &rest[end..];
+    }
+    tokens
+}
+
+/// Find the end of the tag at the start of `html`, skipping quoted attributes
+fn tag_end(html: &str) -> usize {
+    let mut quote = None;
+    for (pos, c) in html.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            (None, '>') => return pos + 1,
+            _ => {}
+        }
+    }
+    html.len()
+}
+
+/// Decode the characters of a text node with their byte offsets, treating each
+/// character reference as a single character
+fn decode_text(text: &str) -> Vec<(usize, char)> {
+    let mut decoded = Vec::with_capacity(text.len());
+    let mut chars = text.char_indices();
+    while let Some((offset, c)) = chars.next() {
+        if c == '&' {
+            if let Some((len, entity)) = decode_entity(&text[offset..]) {
+                decoded.push((offset, entity));
+                chars.nth(len - 2);
+                continue;
+            }
+        }
+        decoded.push((offset, c));
+    }
+    decoded
+}
+
+/// Decode a character reference at the start of `text`, returning its length
+fn decode_entity(text: &str) -> Option<(usize, char)> {
+    let end = text.find(';')?;
+    let name = &text[1..end];
+    if name.is_empty() || !name.chars().all(|c| c == '#' || c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{A0}',
+        _ => match name.strip_prefix('#') {
+            Some(hex) if hex.starts_with(['x', 'X']) => {
+                char::from_u32(u32::from_str_radix(&hex[1..], 16).ok()?)?
+            }
+            Some(dec) => char::from_u32(dec.parse().ok()?)?,
+            None => '\u{FFFD}',
+        },
+    };
+    Some((end + 1, c))
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "model-ja")]
+    use crate::load_default_japanese_parser;
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_translate_html() {
+        let parser = load_default_japanese_parser();
+        assert_eq!(
+            parser.translate_html("<p class=\"a>b\">今日は<b>天気</b>です。</p>"),
+            "<p class=\"a>b\">今日は<b>\u{200B}天気</b>です。</p>"
+        );
+        assert_eq!(
+            parser.translate_html("<p>今日は</p><p>天気です。</p>"),
+            "<p>今日は</p><p>天気です。</p>"
+        );
+        assert_eq!(
+            parser
+                .clone()
+                .with_threshold(-10000.0)
+                .translate_html("a&amp;b"),
+            "a\u{200B}&amp;\u{200B}b"
+        );
+        assert_eq!(
+            parser.translate_html("<code>今日は天気です。</code>"),
+            "<code>今日は天気です。</code>"
+        );
+        assert_eq!(
+            parser.translate_html("<!-- <p>今日は天気です。</p> -->"),
+            "<!-- <p>今日は天気です。</p> -->"
+        );
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_translate_html_in() {
+        let parser = load_default_japanese_parser();
+        let html = "<p>今日は天気です。</p><pre>今日は天気です。</pre>";
+        assert_eq!(
+            parser.translate_html_in(html, &["p"]),
+            "<p>今日は\u{200B}天気です。</p><pre>今日は天気です。</pre>"
+        );
+        assert_eq!(
+            parser.translate_html_in("<P>今日は<b>天気</b>です。</P>", &["p"]),
+            "<P>今日は<b>\u{200B}天気</b>です。</P>"
+        );
+
+        let html =
+            "<p>今日は天気です。<code>今日は天気です。</code></p><div>今日は天気です。</div>";
+        let result = parser.translate_html_in(html, &["p"]);
+        assert!(result.starts_with("<p>今日は\u{200B}天気です。<code>今日は天気です。</code>"));
+        assert!(result.ends_with("<div>今日は天気です。</div>"));
+        assert_eq!(parser.translate_html_in(html, &[]), html);
+
+        // Self-closing and void tags open no element
+        let html = "<p/>今日は天気です。<br>今日は天気です。";
+        assert_eq!(parser.translate_html_in(html, &["p", "br"]), html);
+        assert_eq!(
+            parser.translate_html("<textarea/>今日は天気です。"),
+            "<textarea/>今日は\u{200B}天気です。"
+        );
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_translate_html_ruby() {
+        let parser = load_default_japanese_parser().with_threshold(-10000.0);
+        let html = "<p>私は<ruby>漢字<rt>かんじ</rt></ruby>を読む</p>";
+        let result = parser.translate_html(html);
+        assert!(result.contains("<ruby>\u{200B}漢字<rt>かんじ</rt></ruby>"));
+        assert!(result.contains("</ruby>\u{200B}を"));
+        assert_eq!(result.replace('\u{200B}', ""), html);
+    }
+}
@@ -6,32 +6,86 @@
 //! # Example
 //!
 //! ```
+//! # #[cfg(feature = "model-ja")]
+//! # {
 //! use budoux_rust_wrapper::load_default_japanese_parser;
 //!
 //! let parser = load_default_japanese_parser();
 //! let result = parser.parse("今日は天気です。");
 //! assert_eq!(result, vec!["今日は", "天気です。"]);
+//! # }
 //! ```
 
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError};
 use thiserror::Error;
 
+mod html;
+
 /// Error type for BudouX operations
 #[derive(Error, Debug)]
 pub enum BudouXError {
     #[error("Failed to load model: {0}")]
     ModelLoadError(String),
+    #[error("Unsupported language: {0}")]
+    UnsupportedLanguage(String),
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+    #[error("Input is empty")]
+    EmptyInput,
+    #[error("I/O error: {0}")]
+    IoError(String),
 }
 
 type Result<T> = std::result::Result<T, BudouXError>;
 
+/// Zero-width space, the default break opportunity marker for plain text
+pub const ZWSP: &str = "\u{200B}";
+
+/// Soft hyphen, a break opportunity marker that renders a hyphen when used
+pub const SOFT_HYPHEN: &str = "\u{00AD}";
+
 /// Feature type in the model
 type Feature = HashMap<String, i32>;
 
+/// Feature groups with at most this many entries are searched as sorted
+/// vectors, which beat hashing for tiny custom models
+const SORTED_FEATURE_LIMIT: usize = 64;
+
+// Lookup table the parser uses for one feature group
+#[derive(Debug, Clone)]
+enum FeatureTable {
+    // Look keys up in the model's hash map
+    Hashed,
+    // Binary search a copy of the group's entries sorted by key
+    Sorted(Vec<(String, i32)>),
+}
+
+impl FeatureTable {
+    // Helper method to pick the representation for a feature map by its size
+    fn for_feature(feature: &Feature) -> Self {
+        if feature.len() > SORTED_FEATURE_LIMIT {
+            return FeatureTable::Hashed;
+        }
+        let mut entries: Vec<(String, i32)> = feature
+            .iter()
+            .map(|(key, &score)| (key.clone(), score))
+            .collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        FeatureTable::Sorted(entries)
+    }
+}
+
 /// Model type containing feature scores
-#[derive(Serialize, Deserialize, Debug, Clone)]
+///
+/// Missing feature groups load as empty maps, and groups this crate does not
+/// know are kept in `extra`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
 pub struct Model {
     /// Unigram features with window size 1
     #[serde(rename = "UW1")]
@@ -72,176 +126,5279 @@ pub struct Model {
     /// Trigram features with window size 4
     #[serde(rename = "TW4")]
     pub tw4: Feature,
+    /// Feature groups not used for scoring, keyed by their JSON name
+    #[serde(flatten)]
+    pub extra: HashMap<String, Feature>,
 }
 
-/// The Japanese model data embedded in the binary
-static JAPANESE_MODEL: Lazy<Model> = Lazy::new(|| {
-    let model_json = include_str!("models/ja.json");
-    serde_json::from_str(model_json).expect("Failed to parse Japanese model")
-});
+/// Feature group of the model, named as in the model JSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FeatureGroup {
+    UW1,
+    UW2,
+    UW3,
+    UW4,
+    UW5,
+    UW6,
+    BW1,
+    BW2,
+    BW3,
+    TW1,
+    TW2,
+    TW3,
+    TW4,
+}
 
-/// BudouX parser for segmenting text
+impl FeatureGroup {
+    /// All feature groups in scoring order
+    pub const ALL: [FeatureGroup; 13] = [
+        FeatureGroup::UW1,
+        FeatureGroup::UW2,
+        FeatureGroup::UW3,
+        FeatureGroup::UW4,
+        FeatureGroup::UW5,
+        FeatureGroup::UW6,
+        FeatureGroup::BW1,
+        FeatureGroup::BW2,
+        FeatureGroup::BW3,
+        FeatureGroup::TW1,
+        FeatureGroup::TW2,
+        FeatureGroup::TW3,
+        FeatureGroup::TW4,
+    ];
+
+    // Helper method to get the start of the group's window relative to the
+    // boundary and its length in characters
+    fn window(&self) -> (isize, usize) {
+        match self {
+            FeatureGroup::UW1 => (-3, 1),
+            FeatureGroup::UW2 => (-2, 1),
+            FeatureGroup::UW3 => (-1, 1),
+            FeatureGroup::UW4 => (0, 1),
+            FeatureGroup::UW5 => (1, 1),
+            FeatureGroup::UW6 => (2, 1),
+            FeatureGroup::BW1 => (-2, 2),
+            FeatureGroup::BW2 => (-1, 2),
+            FeatureGroup::BW3 => (0, 2),
+            FeatureGroup::TW1 => (-3, 3),
+            FeatureGroup::TW2 => (-2, 3),
+            FeatureGroup::TW3 => (-1, 3),
+            FeatureGroup::TW4 => (0, 3),
+        }
+    }
+
+    /// Name of the group as used in the model JSON
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FeatureGroup::UW1 => "UW1",
+            FeatureGroup::UW2 => "UW2",
+            FeatureGroup::UW3 => "UW3",
+            FeatureGroup::UW4 => "UW4",
+            FeatureGroup::UW5 => "UW5",
+            FeatureGroup::UW6 => "UW6",
+            FeatureGroup::BW1 => "BW1",
+            FeatureGroup::BW2 => "BW2",
+            FeatureGroup::BW3 => "BW3",
+            FeatureGroup::TW1 => "TW1",
+            FeatureGroup::TW2 => "TW2",
+            FeatureGroup::TW3 => "TW3",
+            FeatureGroup::TW4 => "TW4",
+        }
+    }
+}
+
+/// Breakdown of the score of one boundary
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundaryExplanation {
+    /// Score every boundary starts from
+    pub base_score: f64,
+    /// Key looked up in each group whose window fits the input, with the
+    /// score it added (0.0 when the key is not in the model)
+    pub contributions: Vec<(FeatureGroup, String, f64)>,
+    /// Base score plus all contributions
+    pub score: f64,
+    /// Whether the parser breaks at this boundary
+    pub is_break: bool,
+}
+
+/// Entry counts of a model's feature groups
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelStats {
+    /// Number of entries in each group, in [`FeatureGroup::ALL`] order
+    pub entries: [usize; 13],
+}
+
+impl ModelStats {
+    /// Number of entries in the given group
+    pub fn entries_in(&self, group: FeatureGroup) -> usize {
+        self.entries[group as usize]
+    }
+
+    /// Number of entries across all groups
+    pub fn total(&self) -> usize {
+        self.entries.iter().sum()
+    }
+}
+
+/// Diagnostics collected while loading a model file
 #[derive(Debug, Clone)]
-pub struct Parser {
-    model: Model,
+pub struct LoadReport {
+    /// Size of the model file in bytes
+    pub file_size: u64,
+    /// Time spent deserializing and validating the model
+    pub parse_duration: std::time::Duration,
+    /// Entry counts of the loaded model
+    pub stats: ModelStats,
 }
 
-impl Parser {
-    /// Create a new parser with the given model
-    pub fn new(model: Model) -> Self {
-        Self { model }
+impl Model {
+    /// Create a model without any features
+    pub fn empty() -> Self {
+        Self::default()
     }
 
-    /// Parse the input sentence and return a list of semantic chunks
-    pub fn parse(&self, sentence: &str) -> Vec<String> {
-        if sentence.is_empty() {
-            return Vec::new();
+    /// Check whether every feature map is empty
+    pub fn is_empty(&self) -> bool {
+        FeatureGroup::ALL
+            .iter()
+            .all(|&group| self.feature(group).is_empty())
+    }
+
+    /// Check that every key has as many characters as its group expects
+    ///
+    /// Unigram keys must be one character long, bigram keys two and trigram
+    /// keys three.
+    pub fn validate(&self) -> Result<()> {
+        for group in FeatureGroup::ALL {
+            let expected = match group {
+                FeatureGroup::UW1
+                | FeatureGroup::UW2
+                | FeatureGroup::UW3
+                | FeatureGroup::UW4
+                | FeatureGroup::UW5
+                | FeatureGroup::UW6 => 1,
+                FeatureGroup::BW1 | FeatureGroup::BW2 | FeatureGroup::BW3 => 2,
+                _ => 3,
+            };
+            if let Some(key) = self
+                .feature(group)
+                .keys()
+                .find(|key| key.chars().count() != expected)
+            {
+                return Err(BudouXError::ModelLoadError(format!(
+                    "{} key {:?} has {} characters, expected {}",
+                    group.as_str(),
+                    key,
+                    key.chars().count(),
+                    expected
+                )));
+            }
         }
+        Ok(())
+    }
 
-        let chars: Vec<char> = sentence.chars().collect();
-        let mut chunks = vec![chars[0].to_string()];
+    /// Count the entries of every feature group
+    pub fn stats(&self) -> ModelStats {
+        ModelStats {
+            entries: FeatureGroup::ALL.map(|group| self.feature(group).len()),
+        }
+    }
 
-        // Calculate base score
-        let base_score = -self.calculate_base_score() * 0.5;
+    /// Return the score stored for `key` in the given feature group
+    pub fn feature_score(&self, group: FeatureGroup, key: &str) -> Option<i32> {
+        self.feature(group).get(key).copied()
+    }
 
-        for i in 1..chars.len() {
-            let mut score = base_score;
+    /// Remove the entries whose absolute score is below `min_abs`, returning
+    /// how many were removed
+    ///
+    /// Groups in `extra` are left alone.
+    pub fn prune(&mut self, min_abs: i32) -> usize {
+        let mut removed = 0;
+        for group in FeatureGroup::ALL {
+            let feature = self.feature_mut(group);
+            let before = feature.len();
+            feature.retain(|_, score| score.unsigned_abs() >= min_abs.unsigned_abs());
+            removed += before - feature.len();
+        }
+        removed
+    }
 
-            // UW1: 3 characters before
-            if i > 2 {
-                score += self.get_feature_score(&self.model.uw1, &chars[i - 3].to_string());
+    /// Average the scores of several models, key by key
+    ///
+    /// A key missing from a model counts as 0 for it, and means are rounded
+    /// to the nearest integer. Groups in `extra` are not carried over.
+    pub fn average(models: &[&Model]) -> Model {
+        let mut averaged = Model::empty();
+        if models.is_empty() {
+            return averaged;
+        }
+        for group in FeatureGroup::ALL {
+            let mut sums: HashMap<&str, i64> = HashMap::new();
+            for model in models {
+                for (key, &score) in model.feature(group) {
+                    *sums.entry(key).or_insert(0) += i64::from(score);
+                }
             }
+            averaged
+                .feature_mut(group)
+                .extend(sums.into_iter().map(|(key, sum)| {
+                    let mean = (sum as f64 / models.len() as f64).round() as i32;
+                    (key.to_string(), mean)
+                }));
+        }
+        averaged
+    }
 
-            // UW2: 2 characters before
-            if i > 1 {
-                score += self.get_feature_score(&self.model.uw2, &chars[i - 2].to_string());
-            }
+    // Helper method to get the mutable feature map of a group
+    fn feature_mut(&mut self, group: FeatureGroup) -> &mut Feature {
+        match group {
+            FeatureGroup::UW1 => &mut self.uw1,
+            FeatureGroup::UW2 => &mut self.uw2,
+            FeatureGroup::UW3 => &mut self.uw3,
+            FeatureGroup::UW4 => &mut self.uw4,
+            FeatureGroup::UW5 => &mut self.uw5,
+            FeatureGroup::UW6 => &mut self.uw6,
+            FeatureGroup::BW1 => &mut self.bw1,
+            FeatureGroup::BW2 => &mut self.bw2,
+            FeatureGroup::BW3 => &mut self.bw3,
+            FeatureGroup::TW1 => &mut self.tw1,
+            FeatureGroup::TW2 => &mut self.tw2,
+            FeatureGroup::TW3 => &mut self.tw3,
+            FeatureGroup::TW4 => &mut self.tw4,
+        }
+    }
 
-            // UW3: 1 character before
-            score += self.get_feature_score(&self.model.uw3, &chars[i - 1].to_string());
+    // Helper method to get the feature map of a group
+    fn feature(&self, group: FeatureGroup) -> &Feature {
+        match group {
+            FeatureGroup::UW1 => &self.uw1,
+            FeatureGroup::UW2 => &self.uw2,
+            FeatureGroup::UW3 => &self.uw3,
+            FeatureGroup::UW4 => &self.uw4,
+            FeatureGroup::UW5 => &self.uw5,
+            FeatureGroup::UW6 => &self.uw6,
+            FeatureGroup::BW1 => &self.bw1,
+            FeatureGroup::BW2 => &self.bw2,
+            FeatureGroup::BW3 => &self.bw3,
+            FeatureGroup::TW1 => &self.tw1,
+            FeatureGroup::TW2 => &self.tw2,
+            FeatureGroup::TW3 => &self.tw3,
+            FeatureGroup::TW4 => &self.tw4,
+        }
+    }
+}
 
-            // UW4: current character
-            score += self.get_feature_score(&self.model.uw4, &chars[i].to_string());
+/// The Japanese model data embedded in the binary, or the reason it could
+/// not be parsed
+#[cfg(feature = "model-ja")]
+static JAPANESE_MODEL: Lazy<std::result::Result<Model, String>> =
+    Lazy::new(|| parse_embedded_model(include_str!("models/ja.json")));
 
-            // UW5: 1 character after
-            if i + 1 < chars.len() {
-                score += self.get_feature_score(&self.model.uw5, &chars[i + 1].to_string());
-            }
+/// Parse the JSON of an embedded model, keeping the error message so it can
+/// be reported on every load
+#[cfg_attr(not(feature = "model-ja"), allow(dead_code))]
+fn parse_embedded_model(json: &str) -> std::result::Result<Model, String> {
+    serde_json::from_str(json).map_err(|e| e.to_string())
+}
 
-            // UW6: 2 characters after
-            if i + 2 < chars.len() {
-                score += self.get_feature_score(&self.model.uw6, &chars[i + 2].to_string());
-            }
+/// Return the embedded Japanese model
+#[cfg(feature = "model-ja")]
+fn japanese_model() -> Result<&'static Model> {
+    JAPANESE_MODEL
+        .as_ref()
+        .map_err(|e| BudouXError::ModelLoadError(e.clone()))
+}
 
-            // BW1: 2 characters before (bigram)
-            if i > 1 {
-                let bigram = format!("{}{}", chars[i - 2], chars[i - 1]);
-                score += self.get_feature_score(&self.model.bw1, &bigram);
-            }
+/// Full-width forms of the half-width katakana block (U+FF61..=U+FF9F)
+const HALFWIDTH_KANA_TABLE: [char; 63] = [
+    '。', '「', '」', '、', '・', 'ヲ', 'ァ', 'ィ', 'ゥ', 'ェ', 'ォ', 'ャ', 'ュ', 'ョ', 'ッ', 'ー',
+    'ア', 'イ', 'ウ', 'エ', 'オ', 'カ', 'キ', 'ク', 'ケ', 'コ', 'サ', 'シ', 'ス', 'セ', 'ソ', 'タ',
+    'チ', 'ツ', 'テ', 'ト', 'ナ', 'ニ', 'ヌ', 'ネ', 'ノ', 'ハ', 'ヒ', 'フ', 'ヘ', 'ホ', 'マ', 'ミ',
+    'ム', 'メ', 'モ', 'ヤ', 'ユ', 'ヨ', 'ラ', 'リ', 'ル', 'レ', 'ロ', 'ワ', 'ン', '゛', '゜',
+];
 
-            // BW2: 1 character before and current (bigram)
-            let bigram = format!("{}{}", chars[i - 1], chars[i]);
-            score += self.get_feature_score(&self.model.bw2, &bigram);
+/// Map a half-width katakana character to its full-width form
+fn fold_halfwidth_kana(c: char) -> char {
+    match c {
+        '\u{FF61}'..='\u{FF9F}' => HALFWIDTH_KANA_TABLE[c as usize - 0xFF61],
+        _ => c,
+    }
+}
 
-            // BW3: current and 1 character after (bigram)
-            if i + 1 < chars.len() {
-                let bigram = format!("{}{}", chars[i], chars[i + 1]);
-                score += self.get_feature_score(&self.model.bw3, &bigram);
-            }
+/// Compose a full-width kana with a following voiced or semi-voiced sound mark
+fn compose_voiced_kana(base: char, mark: char) -> Option<char> {
+    let offset = match mark {
+        '゛' if "カキクケコサシスセソタチツテトハヒフヘホ".contains(base) => 1,
+        '゛' if base == 'ウ' => return Some('ヴ'),
+        '゜' if "ハヒフヘホ".contains(base) => 2,
+        _ => return None,
+    };
+    char::from_u32(base as u32 + offset)
+}
 
-            // TW1: 3 characters before (trigram)
-            if i > 2 {
-                let trigram = format!("{}{}{}", chars[i - 3], chars[i - 2], chars[i - 1]);
-                score += self.get_feature_score(&self.model.tw1, &trigram);
-            }
+/// Characters used for feature lookups, which may differ from the input
+struct FeatureKeys<'a> {
+    keys: Cow<'a, [char]>,
+    // Index into `keys` of the first key produced by each input character, or
+    // `None` when the character was folded into the previous key. A missing
+    // map means keys and input characters line up one to one.
+    starts: Option<Vec<Option<usize>>>,
+    // Scores of each key in the six unigram groups, filled in by the parser
+    unigrams: Vec<[f64; 6]>,
+}
 
-            // TW2: 2 characters before and current (trigram)
-            if i > 1 {
-                let trigram = format!("{}{}{}", chars[i - 2], chars[i - 1], chars[i]);
-                score += self.get_feature_score(&self.model.tw2, &trigram);
+impl<'a> FeatureKeys<'a> {
+    /// Fold half-width katakana to full-width, composing sound marks
+    fn halfwidth_kana_folded(chars: &[char]) -> Self {
+        let mut keys: Vec<char> = Vec::with_capacity(chars.len());
+        let mut starts = Vec::with_capacity(chars.len());
+        for &c in chars {
+            let folded = fold_halfwidth_kana(c);
+            let composed = match (c, keys.last()) {
+                ('\u{FF9E}' | '\u{FF9F}', Some(&prev)) => compose_voiced_kana(prev, folded),
+                _ => None,
+            };
+            match composed {
+                Some(composed) => {
+                    *keys.last_mut().unwrap() = composed;
+                    starts.push(None);
+                }
+                None => {
+                    starts.push(Some(keys.len()));
+                    keys.push(folded);
+                }
             }
+        }
+        Self {
+            keys: Cow::Owned(keys),
+            starts: Some(starts),
+            unigrams: Vec::new(),
+        }
+    }
 
-            // TW3: 1 character before, current, and 1 character after (trigram)
-            if i + 1 < chars.len() {
-                let trigram = format!("{}{}{}", chars[i - 1], chars[i], chars[i + 1]);
-                score += self.get_feature_score(&self.model.tw3, &trigram);
-            }
+    /// Apply NFKC, or NFC when `compatibility` is false, to each character
+    /// together with its trailing combining marks
+    #[cfg(feature = "unicode-normalization")]
+    fn normalized(chars: &[char], compatibility: bool) -> Self {
+        use unicode_normalization::char::is_combining_mark;
+        use unicode_normalization::UnicodeNormalization;
 
-            // TW4: current and 2 characters after (trigram)
-            if i + 2 < chars.len() {
-                let trigram = format!("{}{}{}", chars[i], chars[i + 1], chars[i + 2]);
-                score += self.get_feature_score(&self.model.tw4, &trigram);
+        let mut keys: Vec<char> = Vec::with_capacity(chars.len());
+        let mut starts = vec![None; chars.len()];
+        let mut start = 0;
+        while start < chars.len() {
+            let mut end = start + 1;
+            while end < chars.len()
+                && (is_combining_mark(chars[end]) || matches!(chars[end], '\u{FF9E}' | '\u{FF9F}'))
+            {
+                end += 1;
             }
-
-            // If score is positive, start a new chunk
-            if score > 0.0 {
-                chunks.push(chars[i].to_string());
+            let first = keys.len();
+            let group = chars[start..end].iter().copied();
+            if compatibility {
+                keys.extend(group.nfkc());
             } else {
-                // Otherwise, append to the last chunk
-                let last_idx = chunks.len() - 1;
-                chunks[last_idx].push(chars[i]);
+                keys.extend(group.nfc());
+            }
+            if keys.len() > first {
+                starts[start] = Some(first);
             }
+            start = end;
         }
+        Self {
+            keys: Cow::Owned(keys),
+            starts: Some(starts),
+            unigrams: Vec::new(),
+        }
+    }
 
-        chunks
+    /// Index of the first key produced by input character `i`
+    fn key_index(&self, i: usize) -> Option<usize> {
+        match &self.starts {
+            Some(starts) => starts[i],
+            None => Some(i),
+        }
     }
+}
 
-    // Helper method to calculate the base score
-    fn calculate_base_score(&self) -> f64 {
-        let mut sum = 0;
-        sum += self.model.uw1.values().sum::<i32>();
-        sum += self.model.uw2.values().sum::<i32>();
-        sum += self.model.uw3.values().sum::<i32>();
-        sum += self.model.uw4.values().sum::<i32>();
-        sum += self.model.uw5.values().sum::<i32>();
-        sum += self.model.uw6.values().sum::<i32>();
-        sum += self.model.bw1.values().sum::<i32>();
-        sum += self.model.bw2.values().sum::<i32>();
-        sum += self.model.bw3.values().sum::<i32>();
-        sum += self.model.tw1.values().sum::<i32>();
-        sum += self.model.tw2.values().sum::<i32>();
-        sum += self.model.tw3.values().sum::<i32>();
-        sum += self.model.tw4.values().sum::<i32>();
-        sum as f64
+/// Escape the characters with special meaning in HTML text and attributes
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
     }
+    escaped
+}
 
-    // Helper method to get a feature score
-    fn get_feature_score(&self, feature: &Feature, key: &str) -> f64 {
-        feature.get(key).copied().unwrap_or(0) as f64
+/// Merge chunks shorter than `min` characters into a neighbor
+fn merge_short_chunks(
+    sentence: &str,
+    chunks: Vec<std::ops::Range<usize>>,
+    min: usize,
+) -> Vec<std::ops::Range<usize>> {
+    let len = |range: &std::ops::Range<usize>| chunk_len(&sentence[range.clone()]);
+    let mut merged: Vec<std::ops::Range<usize>> = Vec::with_capacity(chunks.len());
+    for chunk in chunks {
+        match merged.last_mut() {
+            Some(last) if len(&chunk) < min || len(last) < min => last.end = chunk.end,
+            _ => merged.push(chunk),
+        }
     }
+    merged
 }
 
-/// Load a parser with the default Japanese model
-pub fn load_default_japanese_parser() -> Parser {
-    Parser::new(JAPANESE_MODEL.clone())
+/// Length of a chunk in characters
+fn chunk_len(chunk: &str) -> usize {
+    chunk.chars().count()
 }
 
-/// Load a parser from a JSON file
-pub fn load_parser_from_file(path: &str) -> Result<Parser> {
-    let model_json =
-        std::fs::read_to_string(path).map_err(|e| BudouXError::ModelLoadError(e.to_string()))?;
+/// Append whitespace to the segments, extending a trailing separator
+fn push_whitespace(segments: &mut Vec<Segment>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    match segments.last_mut() {
+        Some(Segment::Whitespace(last)) => last.push_str(text),
+        _ => segments.push(Segment::Whitespace(text.to_string())),
+    }
+}
 
-    let model: Model = serde_json::from_str(&model_json)
-        .map_err(|e| BudouXError::ModelLoadError(e.to_string()))?;
+/// Script category of a character or chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScriptKind {
+    /// Han, hiragana or katakana letters
+    Cjk,
+    /// Latin letters, including full-width forms
+    Latin,
+    /// Neither script holds a majority of the characters
+    Mixed,
+    /// Digits, punctuation, symbols and other scripts
+    Other,
+}
 
-    Ok(Parser::new(model))
+impl ScriptKind {
+    /// Classify a chunk by the script of the majority of its characters
+    ///
+    /// A chunk is `Mixed` when none of `Cjk`, `Latin` or `Other` covers more
+    /// than half of its characters.
+    pub fn of(text: &str) -> Self {
+        let (mut cjk, mut latin, mut other) = (0, 0, 0);
+        for c in text.chars() {
+            match char_script(c) {
+                ScriptKind::Cjk => cjk += 1,
+                ScriptKind::Latin => latin += 1,
+                _ => other += 1,
+            }
+        }
+        let half = (cjk + latin + other) / 2;
+        if cjk > half {
+            ScriptKind::Cjk
+        } else if latin > half {
+            ScriptKind::Latin
+        } else if other > half || cjk + latin == 0 {
+            ScriptKind::Other
+        } else {
+            ScriptKind::Mixed
+        }
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Classify a character as a CJK letter, a Latin letter, or anything else
+fn char_script(c: char) -> ScriptKind {
+    match c {
+        '\u{3005}'..='\u{3007}'
+        | '\u{3040}'..='\u{309F}'
+        | '\u{30A0}'..='\u{30FF}'
+        | '\u{31F0}'..='\u{31FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FF66}'..='\u{FF9F}'
+        | '\u{20000}'..='\u{2FFFF}' => ScriptKind::Cjk,
+        'A'..='Z' | 'a'..='z' | '\u{FF21}'..='\u{FF3A}' | '\u{FF41}'..='\u{FF5A}' => {
+            ScriptKind::Latin
+        }
+        '\u{00C0}'..='\u{024F}' if c.is_alphabetic() => ScriptKind::Latin,
+        _ => ScriptKind::Other,
+    }
+}
 
-    #[test]
-    fn test_japanese_parser() {
-        let parser = load_default_japanese_parser();
-        let result = parser.parse("今日は天気です。");
-        assert_eq!(result, vec!["今日は", "天気です。"]);
+/// Check whether two adjacent characters switch between Latin and CJK
+fn is_script_change(prev: char, next: char) -> bool {
+    matches!(
+        (char_script(prev), char_script(next)),
+        (ScriptKind::Cjk, ScriptKind::Latin) | (ScriptKind::Latin, ScriptKind::Cjk)
+    )
+}
+
+/// Check whether a character is punctuation that usually ends a phrase
+fn is_phrase_punctuation(c: char) -> bool {
+    matches!(
+        c,
+        '、' | '。' | '，' | '．' | '！' | '？' | '；' | '：' | ',' | '.' | '!' | '?' | ';' | ':'
+    )
+}
+
+/// Check whether a character ends a sentence
+fn is_sentence_terminator(c: char) -> bool {
+    matches!(c, '。' | '！' | '？' | '!' | '?')
+}
+
+/// Check whether a character is a closing bracket or quote
+fn is_closing_bracket(c: char) -> bool {
+    matches!(
+        c,
+        '」' | '』' | '）' | '】' | '〕' | '〉' | '》' | '”' | '’' | ')' | ']' | '}'
+    )
+}
+
+/// Check whether a break between two characters violates the kana rules:
+/// none after a small tsu (っ, ッ, ｯ) and none before a long vowel mark (ー, ｰ)
+fn is_kana_kinsoku(prev: char, next: char) -> bool {
+    matches!(prev, 'っ' | 'ッ' | 'ｯ') || matches!(next, 'ー' | 'ｰ')
+}
+
+/// Check whether a character is a variation selector (U+FE00–FE0F or
+/// U+E0100–E01EF), which belongs with the character before it
+fn is_variation_selector(c: char) -> bool {
+    matches!(c, '\u{FE00}'..='\u{FE0F}' | '\u{E0100}'..='\u{E01EF}')
+}
+
+/// Kind of token that can be kept in one chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TokenClass {
+    /// Runs of ASCII letters and digits, such as `Tower` or `iPhone15`
+    AsciiWord,
+    /// Digits, including full-width ones, with `.` or `,` between digits,
+    /// such as `1,234.5`
+    Number,
+    /// URLs and email addresses: runs of ASCII URL characters that contain
+    /// `://`, start with `www.`, or have text on both sides of an `@`.
+    /// Trailing `.`, `,`, `;`, `:`, `!`, `?` and `)` are left out.
+    Url,
+}
+
+/// Check whether a character counts as a digit of a number token
+fn is_number_digit(c: char) -> bool {
+    c.is_ascii_digit() || ('０'..='９').contains(&c)
+}
+
+/// Check whether a character may appear in a URL or email address
+fn is_url_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-._~:/?#[]@!$&'()*+,;=%".contains(c)
+}
+
+/// Check whether a character may be part of a token of one of the classes
+fn is_token_char(c: char, classes: &[TokenClass]) -> bool {
+    classes.iter().any(|class| match class {
+        TokenClass::AsciiWord => c.is_ascii_alphanumeric(),
+        TokenClass::Number => is_number_digit(c) || matches!(c, '.' | ','),
+        TokenClass::Url => is_url_char(c),
+    })
+}
+
+/// Check whether a run of URL characters is a URL or an email address
+fn is_url(run: &[char]) -> bool {
+    let text: String = run.iter().collect();
+    text.contains("://")
+        || text.starts_with("www.")
+        || text
+            .find('@')
+            .is_some_and(|at| at > 0 && text[at + 1..].contains('.'))
+}
+
+/// Mark the characters that continue a token of one of the classes, so that
+/// entry `i` is true when no break may come before character `i`
+///
+/// Returns an empty list when there are no classes.
+fn atomic_joins(chars: &[char], classes: &[TokenClass]) -> Vec<bool> {
+    if classes.is_empty() {
+        return Vec::new();
+    }
+    let mut joins = vec![false; chars.len()];
+    for &class in classes {
+        match class {
+            TokenClass::AsciiWord => {
+                for (i, join) in joins.iter_mut().enumerate().skip(1) {
+                    *join |=
+                        chars[i - 1].is_ascii_alphanumeric() && chars[i].is_ascii_alphanumeric();
+                }
+            }
+            TokenClass::Number => {
+                let digit = |j: usize| chars.get(j).is_some_and(|&c| is_number_digit(c));
+                let separator = |j: usize| matches!(chars[j], '.' | ',');
+                for (i, join) in joins.iter_mut().enumerate().skip(1) {
+                    // A separator is only part of the number between digits
+                    *join |= (digit(i - 1) && digit(i))
+                        || (digit(i - 1) && separator(i) && digit(i + 1))
+                        || (i > 1 && separator(i - 1) && digit(i - 2) && digit(i));
+                }
+            }
+            TokenClass::Url => {
+                let mut start = 0;
+                while start < chars.len() {
+                    if !is_url_char(chars[start]) {
+                        start += 1;
+                        continue;
+                    }
+                    let mut end = start;
+                    while end < chars.len() && is_url_char(chars[end]) {
+                        end += 1;
+                    }
+                    let next = end;
+                    while end > start
+                        && matches!(chars[end - 1], '.' | ',' | ';' | ':' | '!' | '?' | ')')
+                    {
+                        end -= 1;
+                    }
+                    if is_url(&chars[start..end]) {
+                        joins[start + 1..end].fill(true);
+                    }
+                    start = next;
+                }
+            }
+        }
+    }
+    joins
+}
+
+/// Model-free break rule used when the model has no features
+fn is_heuristic_break(prev: char, next: char) -> bool {
+    let after_punctuation =
+        is_phrase_punctuation(prev) && !is_phrase_punctuation(next) && !is_closing_bracket(next);
+    after_punctuation || is_script_change(prev, next)
+}
+
+/// Check whether the character at `i` belongs to a right-to-left run
+#[cfg(feature = "unicode-bidi")]
+fn in_rtl_run(chars: &[char], i: usize) -> bool {
+    use unicode_bidi::{bidi_class, BidiClass};
+
+    let mut base = i;
+    while base > 0 && bidi_class(chars[base]) == BidiClass::NSM {
+        base -= 1;
     }
+    matches!(bidi_class(chars[base]), BidiClass::R | BidiClass::AL)
+}
 
-    #[test]
-    fn test_empty_string() {
-        let parser = load_default_japanese_parser();
-        let result = parser.parse("");
-        assert!(result.is_empty());
+/// Chunks of a sentence together with metadata about the input
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SegmentationResult {
+    /// Chunks in input order
+    pub chunks: Vec<String>,
+    /// Number of chunks
+    pub count: usize,
+    /// Length of the input in characters
+    pub char_len: usize,
+    /// Language code of the parser's model, if known
+    pub lang: Option<String>,
+}
+
+/// A chunk together with its position in the input, as returned by
+/// [`Parser::parse_info`]
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChunkInfo {
+    /// Text of the chunk
+    pub text: String,
+    /// Offset of the chunk start in characters
+    pub char_start: usize,
+    /// Offset of the chunk start in bytes
+    pub byte_start: usize,
+}
+
+/// A piece of the input returned by [`Parser::parse_keep_separators`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// A chunk without its surrounding whitespace
+    Chunk(String),
+    /// Whitespace between chunks
+    Whitespace(String),
+}
+
+impl Segment {
+    /// Return the text of the segment
+    pub fn as_str(&self) -> &str {
+        match self {
+            Segment::Chunk(text) | Segment::Whitespace(text) => text,
+        }
+    }
+}
+
+/// Settings of one scan that a caller may override for a single call
+#[derive(Debug, Clone, Copy)]
+struct ScanOptions {
+    threshold: f64,
+    min_chunk_chars: usize,
+    // Whether the scanned text is the whole input, so that text made only
+    // of whitespace is split at field separators alone
+    whole_input: bool,
+    // Whether the whole input is whitespace, set by `scan_with`
+    blank: bool,
+}
+
+/// Rule that ended a chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BreakReason {
+    /// The boundary score passed the threshold
+    Model,
+    /// A rule forced a break after phrase punctuation such as `、`
+    Punctuation,
+    /// A rule forced a break next to a `\n`
+    Newline,
+    /// Any other forced break, such as a script change
+    Forced,
+    /// The chunk ends the input
+    End,
+}
+
+/// Boolean switches enabled on a [`Parser`], as returned by [`Parser::options`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ParserOptions {
+    /// Also break where the score equals the threshold
+    pub inclusive_threshold: bool,
+    /// Score half-width katakana as full-width
+    pub halfwidth_kana_fold: bool,
+    /// Always break between Latin and CJK letters
+    pub break_on_script_change: bool,
+    /// Never break after a small tsu or before a long vowel mark
+    pub kana_kinsoku: bool,
+    /// Give sentence terminators their own chunk in `parse_sentences`
+    pub detached_terminators: bool,
+    /// Start every boundary from the base score
+    pub base_score: bool,
+    /// Use the punctuation heuristic when the model is empty
+    pub empty_model_fallback: bool,
+    /// Drop a leading byte order mark
+    pub strip_bom: bool,
+    /// Never break inside right-to-left runs
+    #[cfg(feature = "unicode-bidi")]
+    pub keep_bidi_runs: bool,
+    /// Apply NFKC normalization to feature lookups
+    #[cfg(feature = "unicode-normalization")]
+    pub nfkc: bool,
+    /// Compose combining marks for feature lookups
+    #[cfg(feature = "unicode-normalization")]
+    pub nfc: bool,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        ParserOptions {
+            inclusive_threshold: false,
+            halfwidth_kana_fold: false,
+            break_on_script_change: false,
+            kana_kinsoku: false,
+            detached_terminators: false,
+            base_score: true,
+            empty_model_fallback: false,
+            strip_bom: false,
+            #[cfg(feature = "unicode-bidi")]
+            keep_bidi_runs: false,
+            #[cfg(feature = "unicode-normalization")]
+            nfkc: false,
+            #[cfg(feature = "unicode-normalization")]
+            nfc: false,
+        }
+    }
+}
+
+/// BudouX parser for segmenting text
+#[derive(Debug, Clone)]
+pub struct Parser {
+    model: Model,
+    // Lookup table of each group in `FeatureGroup::ALL` order, kept in sync
+    // with `model`
+    tables: [FeatureTable; 13],
+    // Sum of all feature scores, kept in sync with `model`
+    total_score: f64,
+    language: Option<String>,
+    options: ParserOptions,
+    min_chunk_chars: usize,
+    threshold: f64,
+    base_scale: f64,
+    atomic_token_classes: Vec<TokenClass>,
+    always_break_after: Vec<char>,
+    break_before: Vec<char>,
+    field_separators: Vec<char>,
+}
+
+impl Parser {
+    /// Create a new parser with the given model
+    pub fn new(model: Model) -> Self {
+        let tables = FeatureGroup::ALL.map(|group| FeatureTable::for_feature(model.feature(group)));
+        let mut parser = Self {
+            model,
+            tables,
+            total_score: 0.0,
+            language: None,
+            options: ParserOptions::default(),
+            min_chunk_chars: 0,
+            threshold: 0.0,
+            base_scale: 0.5,
+            atomic_token_classes: Vec::new(),
+            always_break_after: Vec::new(),
+            break_before: Vec::new(),
+            field_separators: Vec::new(),
+        };
+        parser.total_score = parser.calculate_base_score();
+        parser
+    }
+
+    /// Create a parser from a JSON model, validating it after deserialization
+    pub fn from_json_str(json: &str) -> Result<Self> {
+        let model: Model =
+            serde_json::from_str(json).map_err(|e| BudouXError::ModelLoadError(e.to_string()))?;
+        model.validate()?;
+
+        Ok(Self::new(model))
+    }
+
+    /// Create a parser from a model produced by `loader`, validating it
+    ///
+    /// Lets models come from any source, such as a database or a cache,
+    /// while loader errors are passed through unchanged.
+    pub fn from_loader<F: FnOnce() -> Result<Model>>(loader: F) -> Result<Self> {
+        let model = loader()?;
+        model.validate()?;
+
+        Ok(Self::new(model))
+    }
+
+    /// Create a parser from the raw bytes of a JSON model
+    pub fn from_json_bytes(bytes: &[u8]) -> Result<Self> {
+        let model_json = std::str::from_utf8(bytes).map_err(|e| {
+            BudouXError::ModelLoadError(format!(
+                "model is not valid UTF-8 (invalid byte at offset {})",
+                e.valid_up_to()
+            ))
+        })?;
+
+        Self::from_json_str(model_json)
+    }
+
+    /// Record the language code of the model, reported in results
+    pub fn with_language(mut self, language: &str) -> Self {
+        self.language = Some(language.to_string());
+        self
+    }
+
+    /// Return the language code of the model, if known
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    /// Return the score a boundary must exceed to start a new chunk
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    /// Return the boolean switches enabled on this parser
+    pub fn options(&self) -> &ParserOptions {
+        &self.options
+    }
+
+    /// Set the score a boundary must exceed to start a new chunk (default 0.0)
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Also break where the score equals the threshold
+    ///
+    /// By default the comparison is exclusive, so a boundary scoring exactly
+    /// the threshold stays attached to the previous chunk.
+    pub fn with_inclusive_threshold(mut self, inclusive: bool) -> Self {
+        self.options.inclusive_threshold = inclusive;
+        self
+    }
+
+    /// Set the factor applied to the sum of all feature scores (default 0.5)
+    ///
+    /// The negated, scaled sum is the starting score of every boundary.
+    pub fn with_base_scale(mut self, scale: f64) -> Self {
+        self.base_scale = scale;
+        self
+    }
+
+    /// Start every boundary from the base score (default true)
+    ///
+    /// The base score is a global bias against breaking, about -1500 with the
+    /// bundled model. Without it, scores are plain feature sums, so every
+    /// boundary with any net positive evidence breaks and the thresholds the
+    /// model was trained for no longer apply. Enabling it again restores the
+    /// scaled base score.
+    pub fn with_base_score(mut self, enabled: bool) -> Self {
+        self.options.base_score = enabled;
+        self
+    }
+
+    /// Return the score every boundary starts from before features are added
+    pub fn effective_base_score(&self) -> f64 {
+        if !self.options.base_score {
+            return 0.0;
+        }
+        -self.total_score * self.base_scale
+    }
+
+    /// Replace the score of one feature and update the base score
+    pub fn set_feature_score(&mut self, group: FeatureGroup, key: &str, score: i32) {
+        self.model.feature_mut(group).insert(key.to_string(), score);
+        self.tables[group as usize] = FeatureTable::for_feature(self.model.feature(group));
+        self.total_score = self.calculate_base_score();
+    }
+
+    /// Prune the model with [`Model::prune`] and update the base score
+    pub fn prune_features(&mut self, min_abs: i32) -> usize {
+        let removed = self.model.prune(min_abs);
+        self.tables =
+            FeatureGroup::ALL.map(|group| FeatureTable::for_feature(self.model.feature(group)));
+        self.total_score = self.calculate_base_score();
+        removed
+    }
+
+    /// Merge chunks shorter than `n` characters into a neighbor
+    ///
+    /// A short chunk is appended to the previous chunk. The first chunk has no
+    /// predecessor, so it absorbs the following chunks until it reaches `n`
+    /// characters. Input shorter than `n` comes back as a single chunk.
+    pub fn with_min_chunk_chars(mut self, n: usize) -> Self {
+        self.min_chunk_chars = n;
+        self
+    }
+
+    /// Score half-width katakana as their full-width equivalents
+    ///
+    /// Only feature lookups see the folded characters; chunks keep the
+    /// original input text. A half-width sound mark is composed with the
+    /// preceding kana (ﾃﾞ is scored as デ) and never starts a chunk.
+    pub fn with_halfwidth_kana_fold(mut self, enabled: bool) -> Self {
+        self.options.halfwidth_kana_fold = enabled;
+        self
+    }
+
+    /// Always break where the script changes between Latin and CJK letters
+    ///
+    /// Digits, punctuation and other symbols never trigger a forced break.
+    pub fn with_break_on_script_change(mut self, enabled: bool) -> Self {
+        self.options.break_on_script_change = enabled;
+        self
+    }
+
+    /// Never break after a small tsu or before a long vowel mark
+    ///
+    /// The protected pairs are any character after `っ`, `ッ` or half-width
+    /// `ｯ`, and `ー` or half-width `ｰ` after any character. The rule
+    /// overrides forced breaks such as [`Parser::with_break_before`].
+    pub fn with_kana_kinsoku(mut self, enabled: bool) -> Self {
+        self.options.kana_kinsoku = enabled;
+        self
+    }
+
+    /// Split sentence terminators off into a chunk of their own in
+    /// [`Parser::parse_sentences`]
+    ///
+    /// The detached chunk holds the trailing terminators and closing
+    /// brackets, such as `。` or `？」`. A chunk made only of them stays as is.
+    pub fn with_detached_terminators(mut self, enabled: bool) -> Self {
+        self.options.detached_terminators = enabled;
+        self
+    }
+
+    /// Never break inside tokens of the given classes
+    ///
+    /// Breaks before and after a token are still decided by the model. Like
+    /// [`Parser::with_kana_kinsoku`], this overrides forced breaks.
+    pub fn with_atomic_token_classes(mut self, classes: &[TokenClass]) -> Self {
+        self.atomic_token_classes = classes.to_vec();
+        self
+    }
+
+    /// Fall back to a simple heuristic when every feature map is empty
+    ///
+    /// The heuristic breaks after punctuation such as `、` and `。` (keeping
+    /// closing brackets and repeated punctuation attached) and between Latin and
+    /// CJK letters.
+    pub fn with_empty_model_fallback(mut self, enabled: bool) -> Self {
+        self.options.empty_model_fallback = enabled;
+        self
+    }
+
+    /// Always start a new chunk right after any of the given characters
+    ///
+    /// The forced break applies wherever the character occurs, whatever the
+    /// model scores, but not after the last character of the input.
+    pub fn with_always_break_after(mut self, chars: &[char]) -> Self {
+        self.always_break_after = chars.to_vec();
+        self
+    }
+
+    /// Always start a new chunk at any of the given characters
+    ///
+    /// The counterpart of [`Parser::with_always_break_after`], suited to
+    /// opening brackets and quotes. A listed character at the start of the
+    /// input needs no break.
+    pub fn with_break_before(mut self, chars: &[char]) -> Self {
+        self.break_before = chars.to_vec();
+        self
+    }
+
+    /// Treat the given characters as field separators
+    ///
+    /// Each separator becomes a chunk of its own, and the text between
+    /// separators is segmented independently, without features reaching
+    /// across. [`Parser::parse_with_scores`] reports positive infinity for
+    /// the boundaries around a separator.
+    pub fn with_field_separators(mut self, separators: &[char]) -> Self {
+        self.field_separators = separators.to_vec();
+        self
+    }
+
+    /// Drop a leading byte order mark (U+FEFF) before parsing
+    ///
+    /// Disabled by default, in which case a BOM becomes a chunk of its own.
+    /// Byte ranges still index into the original input, and
+    /// [`Parser::break_mask`] keeps one flag for the BOM as part of the first
+    /// chunk.
+    pub fn with_strip_bom(mut self, enabled: bool) -> Self {
+        self.options.strip_bom = enabled;
+        self
+    }
+
+    /// Never break inside a run of right-to-left (R/AL) characters
+    ///
+    /// Non-spacing marks directly after an RTL character count as part of
+    /// its run.
+    #[cfg(feature = "unicode-bidi")]
+    pub fn with_keep_bidi_runs(mut self, enabled: bool) -> Self {
+        self.options.keep_bidi_runs = enabled;
+        self
+    }
+
+    /// Apply NFKC normalization to the characters used for feature lookups
+    ///
+    /// Chunks keep the original input text. Breaks are only placed between
+    /// original characters, so a character that normalizes to several
+    /// (such as ㍻) is never split.
+    #[cfg(feature = "unicode-normalization")]
+    pub fn with_nfkc(mut self, enabled: bool) -> Self {
+        self.options.nfkc = enabled;
+        self
+    }
+
+    /// Compose combining marks with their base character for feature lookups
+    ///
+    /// Decomposed input such as か followed by U+3099 is scored like が.
+    /// Chunks keep the original characters and a combining mark never starts
+    /// a chunk. [`Parser::with_nfkc`] takes precedence when both are enabled.
+    #[cfg(feature = "unicode-normalization")]
+    pub fn with_nfc(mut self, enabled: bool) -> Self {
+        self.options.nfc = enabled;
+        self
+    }
+
+    /// Parse the input sentence and return a list of semantic chunks
+    ///
+    /// Input made only of whitespace is split at field separators alone, so
+    /// without separators it comes back as a single chunk even when it
+    /// contains forced break characters. Whitespace within other text is
+    /// split like any other character. Empty input gives an empty list.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(input_len = sentence.len(), chunks = tracing::field::Empty)
+        )
+    )]
+    pub fn parse(&self, sentence: &str) -> Vec<String> {
+        let chunks = self.parse_limited(sentence, usize::MAX);
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("chunks", chunks.len());
+        chunks
+    }
+
+    /// Call `f` with each chunk of the sentence in order
+    ///
+    /// Chunks are borrowed from the input, so no `String` is allocated per
+    /// chunk. The scan itself still allocates, and the byte ranges of all
+    /// chunks are collected before the first call. Empty input never calls
+    /// `f`.
+    pub fn for_each_chunk(&self, sentence: &str, mut f: impl FnMut(&str)) {
+        for range in self.scan(sentence, usize::MAX, None) {
+            f(&sentence[range]);
+        }
+    }
+
+    /// Parse the sentence, returning [`BudouXError::EmptyInput`] for ""
+    pub fn try_parse(&self, sentence: &str) -> Result<Vec<String>> {
+        if sentence.is_empty() {
+            return Err(BudouXError::EmptyInput);
+        }
+
+        Ok(self.parse(sentence))
+    }
+
+    /// Parse UTF-8 encoded bytes without building a `String` first
+    pub fn parse_bytes(&self, bytes: &[u8]) -> Result<Vec<String>> {
+        let sentence = std::str::from_utf8(bytes).map_err(|e| {
+            BudouXError::InvalidInput(format!(
+                "input is not valid UTF-8 (invalid byte at offset {})",
+                e.valid_up_to()
+            ))
+        })?;
+
+        Ok(self.parse(sentence))
+    }
+
+    /// Parse any bytes, replacing invalid UTF-8 sequences with U+FFFD
+    pub fn parse_lossy(&self, bytes: &[u8]) -> Vec<String> {
+        self.parse(&String::from_utf8_lossy(bytes))
+    }
+
+    /// Parse the sentence into at most `max_chunks` chunks
+    ///
+    /// Scoring stops once the limit is reached and the rest of the input is
+    /// appended to the last chunk. A limit of 0 returns an empty list.
+    pub fn parse_limited(&self, sentence: &str, max_chunks: usize) -> Vec<String> {
+        self.scan(sentence, max_chunks, None)
+            .into_iter()
+            .map(|range| sentence[range].to_string())
+            .collect()
+    }
+
+    /// Parse the sentence with `threshold` in place of the parser's own
+    /// threshold, for this call only
+    pub fn parse_with_threshold(&self, sentence: &str, threshold: f64) -> Vec<String> {
+        let options = ScanOptions {
+            threshold,
+            ..self.scan_options()
+        };
+        self.scan_with(sentence, usize::MAX, None, options)
+            .into_iter()
+            .map(|range| sentence[range].to_string())
+            .collect()
+    }
+
+    /// Parse the sentence and return the score of every boundary in one pass
+    ///
+    /// The scores are those of [`Parser::boundary_scores`], computed while
+    /// the chunks are built.
+    pub fn parse_with_scores(&self, sentence: &str) -> (Vec<String>, Vec<f64>) {
+        let mut scores = Vec::with_capacity(sentence.len());
+        let chunks = self
+            .scan(sentence, usize::MAX, Some(&mut scores))
+            .into_iter()
+            .map(|range| sentence[range].to_string())
+            .collect();
+        (chunks, scores)
+    }
+
+    // Helper method to find the byte range of each chunk in the input,
+    // optionally recording the score of each boundary
+    fn scan(
+        &self,
+        sentence: &str,
+        max_chunks: usize,
+        scores: Option<&mut Vec<f64>>,
+    ) -> Vec<std::ops::Range<usize>> {
+        self.scan_with(sentence, max_chunks, scores, self.scan_options())
+    }
+
+    // Helper method to get the parser's own scan settings
+    fn scan_options(&self) -> ScanOptions {
+        ScanOptions {
+            threshold: self.threshold,
+            min_chunk_chars: self.min_chunk_chars,
+            whole_input: true,
+            blank: false,
+        }
+    }
+
+    // Helper method to scan the input with settings that may differ from the
+    // parser's own
+    fn scan_with(
+        &self,
+        sentence: &str,
+        max_chunks: usize,
+        mut scores: Option<&mut Vec<f64>>,
+        options: ScanOptions,
+    ) -> Vec<std::ops::Range<usize>> {
+        let text_start = sentence.len() - self.without_bom(sentence).len();
+        if text_start == sentence.len() || max_chunks == 0 {
+            return Vec::new();
+        }
+        // Whitespace-only input ignores forced breaks and scores
+        let options = ScanOptions {
+            blank: options.whole_input && sentence[text_start..].chars().all(char::is_whitespace),
+            ..options
+        };
+        if self.field_separators.is_empty() {
+            let field = text_start..sentence.len();
+            return self.scan_field(sentence, field, max_chunks, scores, options);
+        }
+
+        let mut chunks = Vec::new();
+        let mut field_start = text_start;
+        for (offset, c) in sentence[text_start..].char_indices() {
+            let offset = text_start + offset;
+            if !self.field_separators.contains(&c) {
+                continue;
+            }
+            if field_start < offset {
+                let field = field_start..offset;
+                chunks.extend(self.scan_field(
+                    sentence,
+                    field,
+                    usize::MAX,
+                    scores.as_deref_mut(),
+                    options,
+                ));
+            }
+            if let Some(scores) = scores.as_deref_mut() {
+                if offset > text_start {
+                    scores.push(f64::INFINITY);
+                }
+            }
+            field_start = offset + c.len_utf8();
+            chunks.push(offset..field_start);
+        }
+        if field_start < sentence.len() {
+            if let Some(scores) = scores.as_deref_mut() {
+                scores.push(f64::INFINITY);
+            }
+            let field = field_start..sentence.len();
+            chunks.extend(self.scan_field(sentence, field, usize::MAX, scores, options));
+        }
+
+        if chunks.len() > max_chunks {
+            chunks.truncate(max_chunks);
+            chunks[max_chunks - 1].end = sentence.len();
+        }
+        chunks
+    }
+
+    // Helper method to split one field of the sentence into chunks
+    fn scan_field(
+        &self,
+        sentence: &str,
+        field: std::ops::Range<usize>,
+        max_chunks: usize,
+        mut scores: Option<&mut Vec<f64>>,
+        options: ScanOptions,
+    ) -> Vec<std::ops::Range<usize>> {
+        let (offsets, chars): (Vec<usize>, Vec<char>) = sentence[field.clone()]
+            .char_indices()
+            .map(|(offset, c)| (field.start + offset, c))
+            .unzip();
+        let keys = self.feature_keys(&chars);
+        let joins = atomic_joins(&chars, &self.atomic_token_classes);
+        let mut chunks = Vec::with_capacity(chars.len() / 4 + 1);
+        chunks.push(field.clone());
+        let mut key = String::with_capacity(12);
+
+        // Calculate base score
+        let base_score = self.effective_base_score();
+
+        for (i, &offset) in offsets.iter().enumerate().skip(1) {
+            // Once the limit is reached, the rest goes into the last chunk
+            if chunks.len() == max_chunks {
+                break;
+            }
+
+            let score = self.boundary_score(&keys, i, base_score, &mut key);
+            if let Some(scores) = scores.as_deref_mut() {
+                scores.push(score.unwrap_or(f64::NEG_INFINITY));
+            }
+            let is_break = !options.blank
+                && score.is_some_and(|score| {
+                    self.is_break(&chars, &joins, i, score, options.threshold)
+                });
+            #[cfg(feature = "log")]
+            log::trace!("boundary {}: score {:?}, break {}", i, score, is_break);
+
+            // If this is a break, end the last chunk and start a new one
+            if is_break {
+                let last_idx = chunks.len() - 1;
+                chunks[last_idx].end = offset;
+                chunks.push(offset..field.end);
+            }
+        }
+
+        if options.min_chunk_chars > 1 {
+            chunks = merge_short_chunks(sentence, chunks, options.min_chunk_chars);
+        }
+
+        chunks
+    }
+
+    /// Parse the sentence, splitting whitespace around chunks into separators
+    ///
+    /// Whitespace inside a chunk stays part of it, and adjacent whitespace
+    /// from neighboring chunks forms a single separator. Concatenating the
+    /// segments gives back the input.
+    pub fn parse_keep_separators(&self, sentence: &str) -> Vec<Segment> {
+        let mut segments = Vec::new();
+        for chunk in self.parse(sentence) {
+            let core = chunk.trim_start();
+            push_whitespace(&mut segments, &chunk[..chunk.len() - core.len()]);
+            let trimmed = core.trim_end();
+            if !trimmed.is_empty() {
+                segments.push(Segment::Chunk(trimmed.to_string()));
+            }
+            push_whitespace(&mut segments, &core[trimmed.len()..]);
+        }
+        segments
+    }
+
+    /// Parse the sentence and split any chunk longer than `max_bytes` bytes
+    ///
+    /// Oversized chunks are cut greedily at character boundaries. A single
+    /// character that is longer than `max_bytes` still forms its own chunk.
+    pub fn parse_max_bytes(&self, sentence: &str, max_bytes: usize) -> Vec<String> {
+        let mut chunks = Vec::new();
+        for chunk in self.parse(sentence) {
+            if chunk.len() <= max_bytes {
+                chunks.push(chunk);
+                continue;
+            }
+            let mut piece = String::new();
+            for c in chunk.chars() {
+                if !piece.is_empty() && piece.len() + c.len_utf8() > max_bytes {
+                    chunks.push(std::mem::take(&mut piece));
+                }
+                piece.push(c);
+            }
+            chunks.push(piece);
+        }
+        chunks
+    }
+
+    /// Parse a prefix of a longer text, holding back the undecided tail
+    ///
+    /// A boundary looks up to two characters ahead, so breaks within the last
+    /// two characters may still change as input arrives. When characters are
+    /// grouped into one key by normalization or half-width kana folding, the
+    /// last group may still grow and is held back as well, and with atomic
+    /// token classes so is a trailing run of characters that may belong to a
+    /// token. Returns the chunks that end before the undecided tail and the
+    /// remaining text. When short chunks are merged, the chunk before the
+    /// remaining text is held back too, since a short chunk arriving later
+    /// may merge into it.
+    pub fn parse_prefix(&self, sentence: &str) -> (Vec<String>, String) {
+        let ranges = self.parse_ranges(sentence);
+        let settled = self.settled_end(sentence);
+        let mut complete = ranges
+            .iter()
+            .take_while(|range| range.end <= settled)
+            .count();
+        if self.min_chunk_chars > 1 {
+            complete = complete.saturating_sub(1);
+        }
+        let chunks = ranges[..complete]
+            .iter()
+            .map(|range| sentence[range.clone()].to_string())
+            .collect();
+        let pending = ranges[complete..]
+            .iter()
+            .map(|range| &sentence[range.clone()])
+            .collect();
+        (chunks, pending)
+    }
+
+    // Helper method to find the byte offset up to which the chunk boundaries
+    // of `text` can no longer change when more text is appended
+    fn settled_end(&self, text: &str) -> usize {
+        // A boundary reads the key after it and the two following, and with
+        // grouped keys the last of those is only complete once another
+        // group starts
+        let lookahead = if self.continues_key('\u{FF9E}') { 4 } else { 3 };
+        let settled = text
+            .char_indices()
+            .rev()
+            .filter(|&(_, c)| !self.continues_key(c))
+            .nth(lookahead - 1)
+            .map_or(0, |(offset, _)| offset);
+        // Whether a trailing run is a token can depend on all of it
+        settled.min(self.token_run_start(text, text.len()))
+    }
+
+    // Helper method to check whether a character joins the key of the
+    // characters before it
+    fn continues_key(&self, c: char) -> bool {
+        #[cfg(feature = "unicode-normalization")]
+        if self.options.nfkc || self.options.nfc {
+            return unicode_normalization::char::is_combining_mark(c)
+                || matches!(c, '\u{FF9E}' | '\u{FF9F}');
+        }
+        self.options.halfwidth_kana_fold && matches!(c, '\u{FF9E}' | '\u{FF9F}')
+    }
+
+    // Helper method to find the start of the run of characters that may
+    // belong to an atomic token and ends at byte offset `end`
+    fn token_run_start(&self, text: &str, end: usize) -> usize {
+        text[..end]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| is_token_char(c, &self.atomic_token_classes))
+            .last()
+            .map_or(end, |(offset, _)| offset)
+    }
+
+    /// Shorten the sentence to whole chunks totalling at most `max_chars`
+    ///
+    /// The ellipsis is appended only when chunks were dropped and does not
+    /// count towards `max_chars`.
+    pub fn truncate(&self, sentence: &str, max_chars: usize, ellipsis: &str) -> String {
+        let mut truncated = String::new();
+        let mut len = 0;
+        for chunk in self.parse(sentence) {
+            len += chunk_len(&chunk);
+            if len > max_chars {
+                truncated.push_str(ellipsis);
+                return truncated;
+            }
+            truncated.push_str(&chunk);
+        }
+        truncated
+    }
+
+    /// Parse the sentence and group the chunks into sentences
+    ///
+    /// A sentence ends with the chunk whose last character is one of `。`,
+    /// `！`, `？`, `!` or `?`, ignoring trailing closing brackets. See
+    /// [`Parser::with_detached_terminators`] to give the terminator its own
+    /// chunk.
+    pub fn parse_sentences(&self, sentence: &str) -> Vec<Vec<String>> {
+        let mut sentences = Vec::new();
+        let mut current = Vec::new();
+        for mut chunk in self.parse(sentence) {
+            let ends_sentence = chunk
+                .trim_end_matches(is_closing_bracket)
+                .ends_with(is_sentence_terminator);
+            if ends_sentence && self.options.detached_terminators {
+                let body_len = chunk
+                    .trim_end_matches(|c| is_sentence_terminator(c) || is_closing_bracket(c))
+                    .len();
+                if body_len > 0 {
+                    let terminator = chunk.split_off(body_len);
+                    current.push(chunk);
+                    chunk = terminator;
+                }
+            }
+            current.push(chunk);
+            if ends_sentence {
+                sentences.push(std::mem::take(&mut current));
+            }
+        }
+        if !current.is_empty() {
+            sentences.push(current);
+        }
+        sentences
+    }
+
+    /// Parse the sentence and also break before each given character index
+    ///
+    /// Model boundaries and the forced ones are combined. Indices 0 and the
+    /// character count are accepted and have no effect. Returns
+    /// [`BudouXError::InvalidInput`] if an index is greater than the number
+    /// of characters.
+    pub fn parse_with_forced_breaks(
+        &self,
+        sentence: &str,
+        break_char_indices: &[usize],
+    ) -> Result<Vec<String>> {
+        let offsets: Vec<usize> = sentence
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain([sentence.len()])
+            .collect();
+        let char_count = offsets.len() - 1;
+        if let Some(&i) = break_char_indices.iter().find(|&&i| i > char_count) {
+            return Err(BudouXError::InvalidInput(format!(
+                "forced break index {} is out of range for {} characters",
+                i, char_count
+            )));
+        }
+
+        let ranges = self.parse_ranges(sentence);
+        let text_start = ranges.first().map_or(sentence.len(), |range| range.start);
+        let mut starts: Vec<usize> = ranges
+            .iter()
+            .map(|range| range.start)
+            .chain(break_char_indices.iter().map(|&i| offsets[i]))
+            .filter(|&start| start >= text_start && start < sentence.len())
+            .collect();
+        starts.sort_unstable();
+        starts.dedup();
+
+        let ends = starts.iter().skip(1).copied().chain([sentence.len()]);
+        Ok(starts
+            .iter()
+            .zip(ends)
+            .map(|(&start, end)| sentence[start..end].to_string())
+            .collect())
+    }
+
+    /// Parse the sentence and merge chunks until at most `target` remain
+    ///
+    /// Only the `target - 1` highest-scoring boundaries are kept, the earlier
+    /// one winning a tie. Sentences with fewer chunks are returned as parsed,
+    /// and a target of 0 returns an empty list.
+    pub fn parse_to_count(&self, sentence: &str, target: usize) -> Vec<String> {
+        let (chunks, scores) = self.parse_with_scores(sentence);
+        if target == 0 {
+            return Vec::new();
+        }
+        if chunks.len() <= target {
+            return chunks;
+        }
+
+        // Index of each chunk after the first with the score of its boundary
+        let mut boundaries: Vec<(usize, f64)> = Vec::with_capacity(chunks.len() - 1);
+        let mut start = 0;
+        for (idx, chunk) in chunks.iter().enumerate() {
+            if idx > 0 {
+                boundaries.push((idx, scores[start - 1]));
+            }
+            start += chunk_len(chunk);
+        }
+        boundaries.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let mut kept: Vec<usize> = boundaries[..target - 1]
+            .iter()
+            .map(|&(idx, _)| idx)
+            .collect();
+        kept.sort_unstable();
+
+        let mut merged = Vec::with_capacity(target);
+        let mut current = String::new();
+        for (idx, chunk) in chunks.into_iter().enumerate() {
+            if kept.binary_search(&idx).is_ok() {
+                merged.push(std::mem::take(&mut current));
+            }
+            current.push_str(&chunk);
+        }
+        merged.push(current);
+        merged
+    }
+
+    /// Join every window of `n` consecutive chunks
+    ///
+    /// When there are fewer than `n` chunks the whole sentence is the only
+    /// window. `n` of 0 and empty input give an empty list.
+    pub fn chunk_ngrams(&self, sentence: &str, n: usize) -> Vec<String> {
+        let chunks = self.parse(sentence);
+        if n == 0 || chunks.is_empty() {
+            return Vec::new();
+        }
+        if chunks.len() < n {
+            return vec![chunks.concat()];
+        }
+        chunks.windows(n).map(|window| window.concat()).collect()
+    }
+
+    /// Parse the sentence and return the chunks last to first
+    ///
+    /// Only the order of the chunks is reversed; the text inside each chunk
+    /// keeps its logical order, so joining the result does not give back the
+    /// input. This is not bidirectional reordering, and RTL display of
+    /// Japanese text does not need it.
+    pub fn parse_reversed(&self, sentence: &str) -> Vec<String> {
+        let mut chunks = self.parse(sentence);
+        chunks.reverse();
+        chunks
+    }
+
+    /// Parse the sentence, keeping only the chunks accepted by `keep`
+    pub fn parse_filtered(&self, sentence: &str, keep: impl Fn(&str) -> bool) -> Vec<String> {
+        self.scan(sentence, usize::MAX, None)
+            .into_iter()
+            .map(|range| &sentence[range])
+            .filter(|chunk| keep(chunk))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Join chunks with `chunk_sep` and sentences with `sentence_sep`
+    ///
+    /// Sentences are split as in [`Parser::parse_sentences`].
+    pub fn annotate(&self, sentence: &str, chunk_sep: &str, sentence_sep: &str) -> String {
+        self.parse_sentences(sentence)
+            .iter()
+            .map(|chunks| chunks.join(chunk_sep))
+            .collect::<Vec<_>>()
+            .join(sentence_sep)
+    }
+
+    /// Parse the sentence and tag each chunk with its dominant script
+    pub fn parse_tagged(&self, sentence: &str) -> Vec<(String, ScriptKind)> {
+        self.parse(sentence)
+            .into_iter()
+            .map(|chunk| {
+                let kind = ScriptKind::of(&chunk);
+                (chunk, kind)
+            })
+            .collect()
+    }
+
+    /// Parse the sentence, pairing each chunk with the rule that ended it
+    ///
+    /// Forced breaks come from field separators, [`Parser::with_always_break_after`],
+    /// [`Parser::with_break_before`], [`Parser::with_break_on_script_change`]
+    /// and the empty-model fallback. They are reported as `Newline` next to a
+    /// `\n`, as `Punctuation` after phrase punctuation, and as `Forced`
+    /// otherwise.
+    pub fn parse_annotated_breaks(&self, sentence: &str) -> Vec<(String, BreakReason)> {
+        let ranges = self.parse_ranges(sentence);
+        let last = ranges.len().saturating_sub(1);
+        ranges
+            .into_iter()
+            .enumerate()
+            .map(|(idx, range)| {
+                let reason = match (sentence[..range.end].chars().next_back(), idx < last) {
+                    (Some(prev), true) => {
+                        let next = sentence[range.end..].chars().next().unwrap();
+                        self.break_reason(prev, next)
+                    }
+                    _ => BreakReason::End,
+                };
+                (sentence[range].to_string(), reason)
+            })
+            .collect()
+    }
+
+    /// Parse the sentence and return the byte range of each chunk in the input
+    pub fn parse_ranges(&self, sentence: &str) -> Vec<std::ops::Range<usize>> {
+        self.scan(sentence, usize::MAX, None)
+    }
+
+    /// Parse the sentence, pairing each chunk with the 0-based line and
+    /// column of its start
+    ///
+    /// Lines are separated by `\n` and columns count characters. A chunk
+    /// that starts with a line break reports the position of the break.
+    pub fn parse_line_col(&self, sentence: &str) -> Vec<((usize, usize), String)> {
+        let (mut line, mut col) = (0, 0);
+        self.parse(sentence)
+            .into_iter()
+            .map(|chunk| {
+                let start = (line, col);
+                for c in chunk.chars() {
+                    if c == '\n' {
+                        line += 1;
+                        col = 0;
+                    } else {
+                        col += 1;
+                    }
+                }
+                (start, chunk)
+            })
+            .collect()
+    }
+
+    /// Parse the sentence and wrap the chunks with metadata
+    pub fn segment_result(&self, sentence: &str) -> SegmentationResult {
+        let chunks = self.parse(sentence);
+        SegmentationResult {
+            count: chunks.len(),
+            chunks,
+            char_len: sentence.chars().count(),
+            lang: self.language.clone(),
+        }
+    }
+
+    /// Parse the sentence, pairing each chunk with its character and byte
+    /// offsets in the input
+    pub fn parse_info(&self, sentence: &str) -> Vec<ChunkInfo> {
+        // A stripped byte order mark still counts towards the offsets
+        let (mut char_start, mut byte_start) = (0, 0);
+        self.parse_ranges(sentence)
+            .into_iter()
+            .map(|range| {
+                char_start += sentence[byte_start..range.start].chars().count();
+                byte_start = range.start;
+                ChunkInfo {
+                    text: sentence[range].to_string(),
+                    char_start,
+                    byte_start,
+                }
+            })
+            .collect()
+    }
+
+    /// Return the byte offset of every chunk start except the first
+    ///
+    /// Offsets refer to the original string. Inserting a marker at each
+    /// offset, from the last to the first, gives the same text as
+    /// [`Parser::parse_joined`] with that marker, except that a byte order
+    /// mark dropped by [`Parser::with_strip_bom`] is kept.
+    pub fn break_byte_positions(&self, sentence: &str) -> Vec<usize> {
+        let mut ranges = self.parse_ranges(sentence);
+        if !ranges.is_empty() {
+            ranges.remove(0);
+        }
+        ranges.into_iter().map(|range| range.start).collect()
+    }
+
+    /// Parse the sentence and join the chunks with the given separator
+    pub fn parse_joined(&self, sentence: &str, separator: &str) -> String {
+        self.parse(sentence).join(separator)
+    }
+
+    /// Parse the sentence and join the chunks with soft hyphens (U+00AD)
+    ///
+    /// Layout engines show a hyphen where they break at a soft hyphen, which
+    /// suits Latin words but looks out of place in CJK text. Prefer
+    /// [`ZWSP`] with [`Parser::parse_joined`] for mostly Japanese content.
+    pub fn parse_soft_hyphenated(&self, sentence: &str) -> String {
+        self.parse_joined(sentence, SOFT_HYPHEN)
+    }
+
+    /// Parse the sentence into HTML-escaped chunks joined with `<wbr>` tags
+    pub fn to_wbr_html(&self, sentence: &str) -> String {
+        self.parse(sentence)
+            .iter()
+            .map(|chunk| escape_html(chunk))
+            .collect::<Vec<_>>()
+            .join("<wbr>")
+    }
+
+    /// Return one flag per character, true where a chunk starts
+    ///
+    /// The first entry is always true for non-empty input.
+    pub fn break_mask(&self, sentence: &str) -> Vec<bool> {
+        let mut mask = Vec::with_capacity(sentence.len());
+        for chunk in self.parse(sentence) {
+            mask.push(true);
+            mask.extend(std::iter::repeat_n(false, chunk.chars().count() - 1));
+        }
+        if self.without_bom(sentence).len() < sentence.len() {
+            if let Some(first) = mask.first_mut() {
+                *first = false;
+            }
+            mask.insert(0, true);
+        }
+        mask
+    }
+
+    /// Return the 0-based index of the chunk each character belongs to
+    pub fn char_chunk_ids(&self, sentence: &str) -> Vec<usize> {
+        let mut id = 0;
+        self.break_mask(sentence)
+            .into_iter()
+            .enumerate()
+            .map(|(i, starts)| {
+                if starts && i > 0 {
+                    id += 1;
+                }
+                id
+            })
+            .collect()
+    }
+
+    /// Return the chunk with the most characters, the first one on ties
+    pub fn longest_chunk(&self, sentence: &str) -> Option<String> {
+        let mut longest: Option<(usize, std::ops::Range<usize>)> = None;
+        for range in self.scan(sentence, usize::MAX, None) {
+            let len = chunk_len(&sentence[range.clone()]);
+            if longest.as_ref().is_none_or(|(max, _)| len > *max) {
+                longest = Some((len, range));
+            }
+        }
+        longest.map(|(_, range)| sentence[range].to_string())
+    }
+
+    /// Return the char offset and text of the chunk with the fewest
+    /// characters, the first one on ties
+    pub fn shortest_chunk(&self, sentence: &str) -> Option<(usize, String)> {
+        self.parse_info(sentence)
+            .into_iter()
+            .min_by_key(|info| chunk_len(&info.text))
+            .map(|info| (info.char_start, info.text))
+    }
+
+    /// Return the number of breaks per character
+    ///
+    /// Computed as `(chunk count - 1) / character count`, and 0.0 for input
+    /// without any break. A stripped byte order mark is not counted.
+    pub fn break_density(&self, sentence: &str) -> f64 {
+        let chunks = self.parse(sentence).len();
+        if chunks < 2 {
+            return 0.0;
+        }
+        (chunks - 1) as f64 / self.without_bom(sentence).chars().count() as f64
+    }
+
+    /// Check whether the sentence would be split into more than one chunk
+    ///
+    /// Stops scoring at the first break, unless short chunks need merging.
+    pub fn has_break(&self, sentence: &str) -> bool {
+        // A later short chunk may still merge into the first break
+        let max_chunks = if self.min_chunk_chars > 1 {
+            usize::MAX
+        } else {
+            2
+        };
+        self.scan(sentence, max_chunks, None).len() > 1
+    }
+
+    /// Explain the score of the boundary before character `i`
+    ///
+    /// The boundary is scored within its field, as `parse` does. A boundary
+    /// next to a field separator scores positive infinity with no
+    /// contributions. `is_break` does not account for merging short chunks or
+    /// for input made only of whitespace.
+    /// Returns `None` when `i` is 0, past the end, right after a stripped byte
+    /// order mark, or inside a character sequence that normalization folded
+    /// into one key.
+    pub fn explain_boundary(&self, sentence: &str, i: usize) -> Option<BoundaryExplanation> {
+        let bom = sentence.chars().count() - self.without_bom(sentence).chars().count();
+        let text: Vec<char> = self.without_bom(sentence).chars().collect();
+        let i = i.checked_sub(bom)?;
+        if i == 0 || i >= text.len() {
+            return None;
+        }
+        let base_score = self.effective_base_score();
+        if self.field_separators.contains(&text[i - 1]) || self.field_separators.contains(&text[i])
+        {
+            return Some(BoundaryExplanation {
+                base_score,
+                contributions: Vec::new(),
+                score: f64::INFINITY,
+                is_break: true,
+            });
+        }
+        let start = text[..i]
+            .iter()
+            .rposition(|c| self.field_separators.contains(c))
+            .map_or(0, |pos| pos + 1);
+        let end = text[i..]
+            .iter()
+            .position(|c| self.field_separators.contains(c))
+            .map_or(text.len(), |pos| i + pos);
+        let chars = &text[start..end];
+        let i = i - start;
+        let keys = self.feature_keys(chars);
+        let k = keys.key_index(i).filter(|&k| k > 0)?;
+
+        let contributions = self.contributions(&keys.keys, k);
+        let score = contributions
+            .iter()
+            .fold(base_score, |sum, (_, _, score)| sum + score);
+
+        Some(BoundaryExplanation {
+            base_score,
+            contributions,
+            score,
+            is_break: self.is_break(
+                chars,
+                &atomic_joins(chars, &self.atomic_token_classes),
+                i,
+                score,
+                self.threshold,
+            ),
+        })
+    }
+
+    // Helper method to look up the window of every feature group around the
+    // boundary before key `k`, leaving out windows past either edge
+    fn contributions(&self, keys: &[char], k: usize) -> Vec<(FeatureGroup, String, f64)> {
+        FeatureGroup::ALL
+            .iter()
+            .filter_map(|&group| {
+                let (offset, len) = group.window();
+                let start = k.checked_add_signed(offset)?;
+                let window = keys.get(start..start + len)?;
+                let key: String = window.iter().collect();
+                let score = self.get_feature_score(group, &key);
+                Some((group, key, score))
+            })
+            .collect()
+    }
+
+    /// Return the raw score of every potential boundary in the sentence
+    ///
+    /// Entry `i` is the score for a break before character `i + 1` of the
+    /// text after a stripped byte order mark; a break happens when the score
+    /// exceeds the parser's threshold. Boundaries are scored within their
+    /// field as `parse` does, and those next to a field separator score
+    /// positive infinity. Positions inside a character sequence that
+    /// normalization folded into one key score negative infinity.
+    pub fn boundary_scores(&self, sentence: &str) -> Vec<f64> {
+        let mut scores = Vec::with_capacity(sentence.len());
+        self.scan(sentence, usize::MAX, Some(&mut scores));
+        scores
+    }
+
+    /// Return the Shannon entropy, in bits, of the break decisions
+    ///
+    /// Each boundary score is turned into a break probability with the
+    /// logistic function of its distance from the threshold, and the binary
+    /// entropies of all boundaries are summed. Boundaries far from the
+    /// threshold add almost nothing, while a score right at the threshold
+    /// adds one bit. Input with fewer than two characters gives 0.
+    pub fn boundary_entropy(&self, sentence: &str) -> f64 {
+        self.boundary_scores(sentence)
+            .into_iter()
+            .map(|score| {
+                let p = 1.0 / (1.0 + (self.threshold - score).exp());
+                [p, 1.0 - p]
+                    .into_iter()
+                    .filter(|&q| q > 0.0)
+                    .map(|q| -q * q.log2())
+                    .sum::<f64>()
+            })
+            .sum()
+    }
+
+    /// Format the boundary scores as a tab-separated table for debugging
+    ///
+    /// After a `char`, `score` and `break` header there is one row per
+    /// character, holding the character (escaped when it is a control
+    /// character), the score of the boundary before it and whether a chunk
+    /// starts there. Scores are those of [`Parser::boundary_scores`], so the
+    /// first character and a stripped byte order mark have no score.
+    pub fn scores_table(&self, sentence: &str) -> String {
+        let scores = self.boundary_scores(sentence);
+        let mask = self.break_mask(sentence);
+        let bom = sentence.chars().count() - self.without_bom(sentence).chars().count();
+        let mut table = String::from("char\tscore\tbreak\n");
+        for (i, c) in sentence.chars().enumerate() {
+            let score = match i.checked_sub(bom + 1) {
+                Some(j) => scores[j].to_string(),
+                None => String::new(),
+            };
+            table.push_str(&format!("{}\t{}\t{}\n", c.escape_debug(), score, mask[i]));
+        }
+        table
+    }
+
+    /// Parse several sentences, pairing each chunk with the index of its input
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(sentences = sentences.len(), chunks = tracing::field::Empty)
+        )
+    )]
+    pub fn parse_all(&self, sentences: &[&str]) -> Vec<(usize, String)> {
+        let chunks: Vec<(usize, String)> = sentences
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, sentence)| {
+                self.parse(sentence)
+                    .into_iter()
+                    .map(move |chunk| (idx, chunk))
+            })
+            .collect();
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("chunks", chunks.len());
+        chunks
+    }
+
+    // Helper method to build the characters used for feature lookups
+    fn feature_keys<'a>(&self, chars: &'a [char]) -> FeatureKeys<'a> {
+        let mut keys = self.unscored_feature_keys(chars);
+        keys.unigrams = self.unigram_scores(&keys.keys);
+        keys
+    }
+
+    // Helper method to build the lookup characters without their scores
+    fn unscored_feature_keys<'a>(&self, chars: &'a [char]) -> FeatureKeys<'a> {
+        #[cfg(feature = "unicode-normalization")]
+        if self.options.nfkc || self.options.nfc {
+            return FeatureKeys::normalized(chars, self.options.nfkc);
+        }
+
+        if self.options.halfwidth_kana_fold {
+            FeatureKeys::halfwidth_kana_folded(chars)
+        } else {
+            FeatureKeys {
+                keys: Cow::Borrowed(chars),
+                starts: None,
+                unigrams: Vec::new(),
+            }
+        }
+    }
+
+    // Helper method to look up every key in the unigram groups once, reusing
+    // the scores of the previous key for runs of the same character
+    fn unigram_scores(&self, keys: &[char]) -> Vec<[f64; 6]> {
+        let groups = [
+            FeatureGroup::UW1,
+            FeatureGroup::UW2,
+            FeatureGroup::UW3,
+            FeatureGroup::UW4,
+            FeatureGroup::UW5,
+            FeatureGroup::UW6,
+        ];
+        let mut scores: Vec<[f64; 6]> = Vec::with_capacity(keys.len());
+        let mut key = String::with_capacity(4);
+        for (j, &c) in keys.iter().enumerate() {
+            if j > 0 && keys[j - 1] == c {
+                scores.push(scores[j - 1]);
+                continue;
+            }
+            key.clear();
+            key.push(c);
+            scores.push(groups.map(|group| self.get_feature_score(group, &key)));
+        }
+        scores
+    }
+
+    // Helper method to score a break before original character `i`, or `None`
+    // when the character was folded into the key of its predecessor
+    fn boundary_score(
+        &self,
+        keys: &FeatureKeys,
+        i: usize,
+        base_score: f64,
+        key: &mut String,
+    ) -> Option<f64> {
+        match keys.key_index(i) {
+            Some(k) if k > 0 => Some(self.score_at(keys, k, base_score, key)),
+            _ => None,
+        }
+    }
+
+    // Helper method to drop a leading BOM when stripping is enabled
+    fn without_bom<'s>(&self, sentence: &'s str) -> &'s str {
+        if self.options.strip_bom {
+            sentence.strip_prefix('\u{FEFF}').unwrap_or(sentence)
+        } else {
+            sentence
+        }
+    }
+
+    // Helper method to decide whether a chunk starts at `i` given its score,
+    // the token joins from `atomic_joins` and the threshold to apply
+    fn is_break(
+        &self,
+        chars: &[char],
+        joins: &[bool],
+        i: usize,
+        score: f64,
+        threshold: f64,
+    ) -> bool {
+        if self.splits_bidi_run(chars, i) || joins.get(i) == Some(&true) {
+            return false;
+        }
+        if is_variation_selector(chars[i]) {
+            return false;
+        }
+        // Rules look past variation selectors to the character they modify
+        let prev = chars[..i]
+            .iter()
+            .rev()
+            .copied()
+            .find(|&c| !is_variation_selector(c))
+            .unwrap_or(chars[i - 1]);
+        if self.options.kana_kinsoku && is_kana_kinsoku(prev, chars[i]) {
+            return false;
+        }
+        if self.options.break_on_script_change && is_script_change(prev, chars[i]) {
+            return true;
+        }
+        if self.always_break_after.contains(&prev) || self.break_before.contains(&chars[i]) {
+            return true;
+        }
+        if self.options.empty_model_fallback && self.model.is_empty() {
+            return is_heuristic_break(prev, chars[i]);
+        }
+
+        if self.options.inclusive_threshold {
+            score >= threshold
+        } else {
+            score > threshold
+        }
+    }
+
+    // Helper method to name the rule behind a break between two characters
+    fn break_reason(&self, prev: char, next: char) -> BreakReason {
+        let forced = self.field_separators.contains(&prev)
+            || self.field_separators.contains(&next)
+            || self.always_break_after.contains(&prev)
+            || self.break_before.contains(&next)
+            || (self.options.break_on_script_change && is_script_change(prev, next))
+            || (self.options.empty_model_fallback && self.model.is_empty());
+        if !forced {
+            BreakReason::Model
+        } else if prev == '\n' || next == '\n' {
+            BreakReason::Newline
+        } else if is_phrase_punctuation(prev) {
+            BreakReason::Punctuation
+        } else {
+            BreakReason::Forced
+        }
+    }
+
+    // Helper method to check whether a break at `i` would split an RTL run
+    #[cfg(feature = "unicode-bidi")]
+    fn splits_bidi_run(&self, chars: &[char], i: usize) -> bool {
+        self.options.keep_bidi_runs && in_rtl_run(chars, i - 1) && in_rtl_run(chars, i)
+    }
+
+    #[cfg(not(feature = "unicode-bidi"))]
+    fn splits_bidi_run(&self, _chars: &[char], _i: usize) -> bool {
+        false
+    }
+
+    // Helper method to score a break before the character at `i`
+    //
+    // Windows that reach past either end of the input are skipped rather than
+    // filled with sentinel characters. Padding was deliberately left out: the
+    // bundled model has no key containing a control character, so padded
+    // windows could never match and the scores would stay the same, while a
+    // custom model with such keys would start scoring the edges differently.
+    // This has not been checked against captured upstream output.
+    fn score_at(&self, keys: &FeatureKeys, i: usize, base_score: f64, key: &mut String) -> f64 {
+        let unigrams = &keys.unigrams;
+        let keys = &keys.keys[..];
+        let mut score = base_score;
+
+        // UW1: 3 characters before
+        if i > 2 {
+            score += unigrams[i - 3][0];
+        }
+
+        // UW2: 2 characters before
+        if i > 1 {
+            score += unigrams[i - 2][1];
+        }
+
+        // UW3: 1 character before
+        score += unigrams[i - 1][2];
+
+        // UW4: current character
+        score += unigrams[i][3];
+
+        // UW5: 1 character after
+        if i + 1 < keys.len() {
+            score += unigrams[i + 1][4];
+        }
+
+        // UW6: 2 characters after
+        if i + 2 < keys.len() {
+            score += unigrams[i + 2][5];
+        }
+
+        // BW1: 2 characters before (bigram)
+        if i > 1 {
+            score += self.get_ngram_score(FeatureGroup::BW1, key, &keys[i - 2..i]);
+        }
+
+        // BW2: 1 character before and current (bigram)
+        score += self.get_ngram_score(FeatureGroup::BW2, key, &keys[i - 1..i + 1]);
+
+        // BW3: current and 1 character after (bigram)
+        if i + 1 < keys.len() {
+            score += self.get_ngram_score(FeatureGroup::BW3, key, &keys[i..i + 2]);
+        }
+
+        // TW1: 3 characters before (trigram)
+        if i > 2 {
+            score += self.get_ngram_score(FeatureGroup::TW1, key, &keys[i - 3..i]);
+        }
+
+        // TW2: 2 characters before and current (trigram)
+        if i > 1 {
+            score += self.get_ngram_score(FeatureGroup::TW2, key, &keys[i - 2..i + 1]);
+        }
+
+        // TW3: 1 character before, current, and 1 character after (trigram)
+        if i + 1 < keys.len() {
+            score += self.get_ngram_score(FeatureGroup::TW3, key, &keys[i - 1..i + 2]);
+        }
+
+        // TW4: current and 2 characters after (trigram)
+        if i + 2 < keys.len() {
+            score += self.get_ngram_score(FeatureGroup::TW4, key, &keys[i..i + 3]);
+        }
+
+        score
+    }
+
+    /// Collect the unique chunks found across all sentences
+    pub fn collect_vocab(&self, sentences: &[&str]) -> HashSet<String> {
+        sentences
+            .iter()
+            .flat_map(|sentence| self.parse(sentence))
+            .collect()
+    }
+
+    /// Count how often each chunk occurs in the sentence
+    pub fn chunk_frequencies(&self, sentence: &str) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for chunk in self.parse(sentence) {
+            *counts.entry(chunk).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Count the documents of the corpus that contain each chunk
+    ///
+    /// A chunk repeated within one document counts once for it.
+    pub fn corpus_frequencies(&self, corpus: &[&str]) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for document in corpus {
+            for chunk in self.parse(document).into_iter().collect::<HashSet<_>>() {
+                *counts.entry(chunk).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    // Helper method to calculate the base score
+    fn calculate_base_score(&self) -> f64 {
+        // Summed as i64, since the scores of a model may overflow an i32
+        let sum: i64 = FeatureGroup::ALL
+            .iter()
+            .flat_map(|&group| self.model.feature(group).values())
+            .map(|&score| i64::from(score))
+            .sum();
+        sum as f64
+    }
+
+    // Helper method to get a feature score
+    fn get_feature_score(&self, group: FeatureGroup, key: &str) -> f64 {
+        let score = match &self.tables[group as usize] {
+            FeatureTable::Hashed => self.model.feature(group).get(key).copied(),
+            FeatureTable::Sorted(entries) => entries
+                .binary_search_by(|(entry, _)| entry.as_str().cmp(key))
+                .ok()
+                .map(|i| entries[i].1),
+        };
+        score.unwrap_or(0) as f64
+    }
+
+    // Helper method to get an n-gram feature score, reusing `key` as the
+    // lookup buffer instead of allocating a new string per position. The
+    // buffer is cleared first, so one buffer serves every feature group.
+    fn get_ngram_score(&self, group: FeatureGroup, key: &mut String, chars: &[char]) -> f64 {
+        key.clear();
+        key.extend(chars);
+        self.get_feature_score(group, key)
+    }
+}
+
+#[cfg(feature = "smol_str")]
+impl Parser {
+    /// Parse the sentence into small strings, skipping intermediate `String`s
+    ///
+    /// Chunks of up to 23 bytes, which covers most Japanese phrases, are
+    /// stored inline without a heap allocation.
+    pub fn parse_smolstr(&self, sentence: &str) -> Vec<smol_str::SmolStr> {
+        self.scan(sentence, usize::MAX, None)
+            .into_iter()
+            .map(|range| smol_str::SmolStr::new(&sentence[range]))
+            .collect()
+    }
+}
+
+#[cfg(feature = "futures")]
+impl Parser {
+    /// Segment text that arrives as a stream of pieces, yielding each chunk
+    /// as soon as later input can no longer change it
+    ///
+    /// A boundary looks up to two characters ahead and three behind, so the
+    /// stream keeps the undecided tail of the input, decided as in
+    /// [`Parser::parse_prefix`], plus three keys of context, extended to
+    /// the start of a run of characters that may belong to an atomic
+    /// token. The chunks are those [`Parser::parse`] returns for the whole
+    /// input. An input error is passed through and ends the stream.
+    pub fn segment_stream<'a, S>(
+        &'a self,
+        input: S,
+    ) -> impl futures_util::Stream<Item = std::io::Result<String>> + 'a
+    where
+        S: futures_util::Stream<Item = std::io::Result<String>> + 'a,
+    {
+        let state = ChunkStream {
+            input: Box::pin(input),
+            buffer: String::new(),
+            context: 0,
+            ready: std::collections::VecDeque::new(),
+            held: None,
+            done: false,
+        };
+        futures_util::stream::unfold(state, move |mut state| async move {
+            use futures_util::StreamExt;
+
+            loop {
+                if let Some(chunk) = state.ready.pop_front() {
+                    return Some((Ok(chunk), state));
+                }
+                if state.done {
+                    return None;
+                }
+                match state.input.next().await {
+                    Some(Ok(piece)) => {
+                        state.buffer.push_str(&piece);
+                        self.finalize_chunks(&mut state, false);
+                    }
+                    Some(Err(e)) => {
+                        state.done = true;
+                        return Some((Err(e), state));
+                    }
+                    None => {
+                        state.done = true;
+                        self.finalize_chunks(&mut state, true);
+                    }
+                }
+            }
+        })
+    }
+
+    // Helper method to move the chunks that later input cannot change from
+    // the buffer to the ready queue, or all of them at the end of the input
+    fn finalize_chunks<S>(&self, state: &mut ChunkStream<S>, end: bool) {
+        // Merging depends on the following chunks, so it is done here, and
+        // once a chunk is final the input is not all whitespace
+        let options = ScanOptions {
+            min_chunk_chars: 0,
+            whole_input: state.context == 0,
+            ..self.scan_options()
+        };
+        let ranges = self.scan_with(&state.buffer, usize::MAX, None, options);
+        let settled = self.settled_end(&state.buffer);
+        let mut pending = state.context;
+        for range in ranges {
+            if range.end <= state.context {
+                continue;
+            }
+            if !end && range.end > settled {
+                break;
+            }
+            let chunk = state.buffer[range.start.max(state.context)..range.end].to_string();
+            self.push_final_chunk(state, chunk);
+            pending = range.end;
+        }
+
+        if end {
+            state.ready.extend(state.held.take());
+            return;
+        }
+        let context_start = self.context_start(&state.buffer, pending);
+        state.buffer.drain(..context_start);
+        state.context = pending - context_start;
+    }
+
+    // Helper method to find where the text kept as context before `pending`
+    // starts: three keys back, extended to the start of a token run
+    fn context_start(&self, text: &str, pending: usize) -> usize {
+        let start = text[..pending]
+            .char_indices()
+            .rev()
+            .filter(|&(_, c)| !self.continues_key(c))
+            .nth(2)
+            .map_or(0, |(offset, _)| offset);
+        self.token_run_start(text, start)
+    }
+
+    // Helper method to queue a final chunk, merging it like
+    // `merge_short_chunks` when a minimum length is set
+    fn push_final_chunk<S>(&self, state: &mut ChunkStream<S>, chunk: String) {
+        let min = self.min_chunk_chars;
+        if min <= 1 {
+            state.ready.push_back(chunk);
+            return;
+        }
+
+        // Separators are chunks of their own and end the field before them
+        let mut chars = chunk.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if self.field_separators.contains(&c) {
+                state.ready.extend(state.held.take());
+                state.ready.push_back(chunk);
+                return;
+            }
+        }
+        match state.held.as_mut() {
+            Some(last) if chunk_len(&chunk) < min || chunk_len(last) < min => last.push_str(&chunk),
+            _ => {
+                state.ready.extend(state.held.take());
+                state.held = Some(chunk);
+            }
+        }
+    }
+}
+
+/// State of [`Parser::segment_stream`] between input pieces
+#[cfg(feature = "futures")]
+struct ChunkStream<S> {
+    input: std::pin::Pin<Box<S>>,
+    // Last characters of the finalized text followed by the undecided text
+    buffer: String,
+    // Byte length of the finalized context at the start of `buffer`
+    context: usize,
+    // Final chunks not yet yielded
+    ready: std::collections::VecDeque<String>,
+    // Last final chunk, kept while later chunks may still merge into it
+    held: Option<String>,
+    done: bool,
+}
+
+#[cfg(feature = "textwrap")]
+impl Parser {
+    /// Split a line into `textwrap` words whose boundaries are BudouX chunk
+    /// boundaries, further split at ASCII spaces
+    pub fn textwrap_words<'a>(&self, line: &'a str) -> Vec<textwrap::core::Word<'a>> {
+        let mut words = Vec::new();
+        let mut start = 0;
+        for chunk in self.parse(line) {
+            let end = start + chunk.len();
+            words.extend(textwrap::WordSeparator::AsciiSpace.find_words(&line[start..end]));
+            start = end;
+        }
+        words
+    }
+
+    /// Wrap text to the given display width, breaking only at chunk boundaries
+    /// and spaces
+    pub fn wrap(&self, text: &str, width: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        for line in text.split('\n') {
+            let words = self.textwrap_words(line);
+            if words.is_empty() {
+                lines.push(String::new());
+                continue;
+            }
+            for wrapped in textwrap::wrap_algorithms::wrap_first_fit(&words, &[width as f64]) {
+                let mut out = String::new();
+                for (idx, word) in wrapped.iter().enumerate() {
+                    out.push_str(word.word);
+                    if idx + 1 < wrapped.len() {
+                        out.push_str(word.whitespace);
+                    }
+                }
+                lines.push(out);
+            }
+        }
+        lines
+    }
+
+    /// Pack whole chunks greedily into lines of at most `width` columns
+    ///
+    /// Widths follow East Asian conventions, so full-width characters take
+    /// two columns. Unlike [`Parser::wrap`], spaces are never break points
+    /// of their own: a line ends only between chunks. Lines carry no
+    /// trailing whitespace, wrapped lines no leading whitespace, and a chunk
+    /// wider than `width` gets a line of its own. Each `\n` starts a new line.
+    pub fn wrap_to_lines(&self, sentence: &str, width: usize) -> Vec<String> {
+        use textwrap::core::display_width;
+
+        let mut lines = Vec::new();
+        for paragraph in sentence.split('\n') {
+            let mut line = String::new();
+            for chunk in self.parse(paragraph) {
+                let fits = display_width(&line) + display_width(chunk.trim_end()) <= width;
+                if fits || line.trim().is_empty() {
+                    line.push_str(&chunk);
+                } else {
+                    lines.push(line.trim_end().to_string());
+                    line = chunk.trim_start().to_string();
+                }
+            }
+            lines.push(line.trim_end().to_string());
+        }
+        lines
+    }
+
+    /// Fill text to the given display width, joining wrapped lines with `\n`
+    pub fn fill(&self, text: &str, width: usize) -> String {
+        self.wrap(text, width).join("\n")
+    }
+}
+
+/// Extension trait for segmenting every item of a string iterator
+///
+/// ```
+/// # #[cfg(feature = "model-ja")]
+/// # {
+/// use budoux_rust_wrapper::{load_default_japanese_parser, SegmentExt};
+///
+/// let parser = load_default_japanese_parser();
+/// let chunks: Vec<_> = "今日は天気です。\n私の家".lines().segment_with(&parser).collect();
+/// assert_eq!(chunks, vec![vec!["今日は", "天気です。"], vec!["私の", "家"]]);
+/// # }
+/// ```
+pub trait SegmentExt<'a>: Iterator<Item = &'a str> + Sized {
+    /// Lazily parse each item with the given parser
+    fn segment_with(self, parser: &Parser) -> impl Iterator<Item = Vec<String>> {
+        self.map(move |sentence| parser.parse(sentence))
+    }
+}
+
+impl<'a, I: Iterator<Item = &'a str>> SegmentExt<'a> for I {}
+
+impl std::fmt::Display for Parser {
+    /// Summarize the language, threshold, model size and enabled options
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut options = Vec::new();
+        if self.options.inclusive_threshold {
+            options.push("inclusive_threshold");
+        }
+        if self.options.halfwidth_kana_fold {
+            options.push("halfwidth_kana_fold");
+        }
+        if self.options.break_on_script_change {
+            options.push("break_on_script_change");
+        }
+        if self.options.kana_kinsoku {
+            options.push("kana_kinsoku");
+        }
+        if self.options.detached_terminators {
+            options.push("detached_terminators");
+        }
+        if !self.options.base_score {
+            options.push("no_base_score");
+        }
+        if !self.atomic_token_classes.is_empty() {
+            options.push("atomic_token_classes");
+        }
+        if self.options.empty_model_fallback {
+            options.push("empty_model_fallback");
+        }
+        if self.options.strip_bom {
+            options.push("strip_bom");
+        }
+        if !self.always_break_after.is_empty() {
+            options.push("always_break_after");
+        }
+        if !self.break_before.is_empty() {
+            options.push("break_before");
+        }
+        if !self.field_separators.is_empty() {
+            options.push("field_separators");
+        }
+        #[cfg(feature = "unicode-bidi")]
+        if self.options.keep_bidi_runs {
+            options.push("keep_bidi_runs");
+        }
+        #[cfg(feature = "unicode-normalization")]
+        if self.options.nfkc {
+            options.push("nfkc");
+        }
+        #[cfg(feature = "unicode-normalization")]
+        if self.options.nfc {
+            options.push("nfc");
+        }
+
+        write!(
+            f,
+            "Parser {{ language: {}, threshold: {:?}, entries: {}",
+            self.language.as_deref().unwrap_or("unknown"),
+            self.threshold,
+            self.model.stats().total()
+        )?;
+        if self.min_chunk_chars > 1 {
+            write!(f, ", min_chunk_chars: {}", self.min_chunk_chars)?;
+        }
+        if !options.is_empty() {
+            write!(f, ", options: [{}]", options.join(", "))?;
+        }
+        write!(f, " }}")
+    }
+}
+
+/// Declarative parser settings that can be stored in configuration files
+///
+/// Missing fields take their default values, which match [`Parser::new`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ParserConfig {
+    /// Language code of the bundled model to use
+    pub language: String,
+    /// Score a boundary must exceed to start a new chunk
+    pub threshold: f64,
+    /// Also break where the score equals the threshold
+    pub inclusive_threshold: bool,
+    /// Factor applied to the sum of all feature scores
+    pub base_scale: f64,
+    /// Start every boundary from the base score
+    pub base_score: bool,
+    /// Minimum chunk length in characters
+    pub min_chunk_chars: usize,
+    /// Score half-width katakana as full-width
+    pub halfwidth_kana_fold: bool,
+    /// Always break between Latin and CJK letters
+    pub break_on_script_change: bool,
+    /// Never break after a small tsu or before a long vowel mark
+    pub kana_kinsoku: bool,
+    /// Give sentence terminators their own chunk in `parse_sentences`
+    pub detached_terminators: bool,
+    /// Token classes never broken internally
+    pub atomic_token_classes: Vec<TokenClass>,
+    /// Use the punctuation heuristic when the model is empty
+    pub empty_model_fallback: bool,
+    /// Drop a leading byte order mark
+    pub strip_bom: bool,
+    /// Characters that always end a chunk
+    pub always_break_after: Vec<char>,
+    /// Characters that always start a chunk
+    pub break_before: Vec<char>,
+    /// Characters that separate independently segmented fields
+    pub field_separators: Vec<char>,
+    /// Never break inside right-to-left runs
+    #[cfg(feature = "unicode-bidi")]
+    pub keep_bidi_runs: bool,
+    /// Apply NFKC normalization to feature lookups
+    #[cfg(feature = "unicode-normalization")]
+    pub nfkc: bool,
+    /// Compose combining marks for feature lookups
+    #[cfg(feature = "unicode-normalization")]
+    pub nfc: bool,
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        Self {
+            language: "ja".to_string(),
+            threshold: 0.0,
+            inclusive_threshold: false,
+            base_scale: 0.5,
+            base_score: true,
+            min_chunk_chars: 0,
+            halfwidth_kana_fold: false,
+            break_on_script_change: false,
+            kana_kinsoku: false,
+            detached_terminators: false,
+            atomic_token_classes: Vec::new(),
+            empty_model_fallback: false,
+            strip_bom: false,
+            always_break_after: Vec::new(),
+            break_before: Vec::new(),
+            field_separators: Vec::new(),
+            #[cfg(feature = "unicode-bidi")]
+            keep_bidi_runs: false,
+            #[cfg(feature = "unicode-normalization")]
+            nfkc: false,
+            #[cfg(feature = "unicode-normalization")]
+            nfc: false,
+        }
+    }
+}
+
+impl ParserConfig {
+    /// Build a parser with the configured language and options
+    pub fn build(&self) -> Result<Parser> {
+        let parser = bundled_parser(&self.language)?
+            .with_threshold(self.threshold)
+            .with_inclusive_threshold(self.inclusive_threshold)
+            .with_base_scale(self.base_scale)
+            .with_base_score(self.base_score)
+            .with_min_chunk_chars(self.min_chunk_chars)
+            .with_halfwidth_kana_fold(self.halfwidth_kana_fold)
+            .with_break_on_script_change(self.break_on_script_change)
+            .with_kana_kinsoku(self.kana_kinsoku)
+            .with_detached_terminators(self.detached_terminators)
+            .with_atomic_token_classes(&self.atomic_token_classes)
+            .with_empty_model_fallback(self.empty_model_fallback)
+            .with_strip_bom(self.strip_bom)
+            .with_always_break_after(&self.always_break_after)
+            .with_break_before(&self.break_before)
+            .with_field_separators(&self.field_separators);
+        #[cfg(feature = "unicode-bidi")]
+        let parser = parser.with_keep_bidi_runs(self.keep_bidi_runs);
+        #[cfg(feature = "unicode-normalization")]
+        let parser = parser.with_nfkc(self.nfkc).with_nfc(self.nfc);
+
+        Ok(parser)
+    }
+}
+
+/// Parser that remembers the chunks of the sentences it has parsed
+///
+/// The cache is cleared once it holds `capacity` sentences.
+#[derive(Debug)]
+pub struct CachingParser {
+    parser: Arc<Parser>,
+    cache: HashMap<String, Vec<String>>,
+    capacity: usize,
+}
+
+impl CachingParser {
+    /// Create a caching parser remembering up to `capacity` sentences
+    pub fn new(parser: Arc<Parser>, capacity: usize) -> Self {
+        Self {
+            parser,
+            cache: HashMap::new(),
+            capacity,
+        }
+    }
+
+    /// Parse the sentence like [`Parser::parse`], reusing earlier results
+    pub fn parse(&mut self, sentence: &str) -> Vec<String> {
+        if let Some(chunks) = self.cache.get(sentence) {
+            return chunks.clone();
+        }
+        let chunks = self.parser.parse(sentence);
+        if self.capacity > 0 {
+            if self.cache.len() >= self.capacity {
+                self.cache.clear();
+            }
+            self.cache.insert(sentence.to_string(), chunks.clone());
+        }
+        chunks
+    }
+
+    /// Number of sentences currently cached
+    pub fn cached(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// The parser behind the cache
+    pub fn parser(&self) -> &Parser {
+        &self.parser
+    }
+}
+
+/// Pool of caching parsers handed out in turn to the threads of a server
+///
+/// Every slot shares one `Parser` and keeps its own cache behind a mutex.
+/// `get` takes the first free slot starting from the next one in turn, and
+/// only waits when all of them are in use.
+#[derive(Debug)]
+pub struct ParserPool {
+    slots: Vec<Mutex<CachingParser>>,
+    next: AtomicUsize,
+}
+
+impl ParserPool {
+    /// Number of sentences each slot caches unless set otherwise
+    pub const DEFAULT_CACHE_CAPACITY: usize = 1024;
+
+    /// Create a pool of `size` slots, and at least one
+    pub fn new(parser: Parser, size: usize) -> Self {
+        let parser = Arc::new(parser);
+        let slots = (0..size.max(1))
+            .map(|_| {
+                Mutex::new(CachingParser::new(
+                    Arc::clone(&parser),
+                    Self::DEFAULT_CACHE_CAPACITY,
+                ))
+            })
+            .collect();
+        Self {
+            slots,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Set the number of sentences each slot caches, clearing the caches
+    pub fn with_cache_capacity(mut self, capacity: usize) -> Self {
+        for slot in &mut self.slots {
+            let slot = slot.get_mut().unwrap_or_else(PoisonError::into_inner);
+            *slot = CachingParser::new(Arc::clone(&slot.parser), capacity);
+        }
+        self
+    }
+
+    /// Lock a slot of the pool, preferring one no other thread holds
+    pub fn get(&self) -> MutexGuard<'_, CachingParser> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        for offset in 0..self.slots.len() {
+            let slot = &self.slots[(start + offset) % self.slots.len()];
+            match slot.try_lock() {
+                Ok(guard) => return guard,
+                Err(TryLockError::Poisoned(poisoned)) => return poisoned.into_inner(),
+                Err(TryLockError::WouldBlock) => {}
+            }
+        }
+        self.slots[start % self.slots.len()]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Number of slots in the pool
+    pub fn size(&self) -> usize {
+        self.slots.len()
+    }
+}
+
+/// Share of the absolute feature contributions made by each group
+///
+/// Contributions are summed over every boundary in the corpus, so the values
+/// add up to 1.0 unless no feature matched at all, in which case every group
+/// maps to 0.0.
+pub fn feature_group_importance(parser: &Parser, corpus: &[&str]) -> HashMap<FeatureGroup, f64> {
+    let mut totals: HashMap<FeatureGroup, f64> = FeatureGroup::ALL
+        .iter()
+        .map(|&group| (group, 0.0))
+        .collect();
+    for sentence in corpus {
+        // Boundaries next to a separator have no contributions, as in
+        // `Parser::explain_boundary`
+        let text: Vec<char> = parser.without_bom(sentence).chars().collect();
+        for field in text.split(|c| parser.field_separators.contains(c)) {
+            let keys = parser.unscored_feature_keys(field);
+            for i in 1..field.len() {
+                let Some(k) = keys.key_index(i).filter(|&k| k > 0) else {
+                    continue;
+                };
+                for (group, _, score) in parser.contributions(&keys.keys, k) {
+                    *totals.entry(group).or_default() += score.abs();
+                }
+            }
+        }
+    }
+
+    // Summed in group order so the result does not depend on hash order
+    let sum: f64 = FeatureGroup::ALL.iter().map(|group| totals[group]).sum();
+    if sum > 0.0 {
+        for value in totals.values_mut() {
+            *value /= sum;
+        }
+    }
+    totals
+}
+
+/// Pick the threshold that best reproduces gold-standard segmentations
+///
+/// Each example pairs an input with its expected chunks. Candidate thresholds
+/// sit between consecutive distinct boundary scores, and the one with the
+/// highest boundary F1 over all examples wins, comparing scores the way the
+/// parser's [`Parser::with_inclusive_threshold`] setting does. Ties go to the
+/// candidate closest to the parser's current threshold.
+pub fn calibrate_threshold(parser: &Parser, examples: &[(&str, &[&str])]) -> f64 {
+    let mut labeled = Vec::new();
+    for (sentence, gold) in examples {
+        let gold_breaks = chunk_boundaries(gold);
+        for (idx, score) in parser.boundary_scores(sentence).into_iter().enumerate() {
+            labeled.push((score, gold_breaks.contains(&(idx + 1))));
+        }
+    }
+
+    // Infinite scores of separators and folded keys ignore the threshold
+    let mut scores: Vec<f64> = labeled
+        .iter()
+        .map(|(score, _)| *score)
+        .filter(|score| score.is_finite())
+        .collect();
+    if scores.is_empty() {
+        return parser.threshold;
+    }
+    scores.sort_by(|a, b| a.total_cmp(b));
+    scores.dedup();
+    let max = scores[scores.len() - 1];
+    let inclusive = parser.options.inclusive_threshold;
+    let mut candidates = vec![scores[0] - 1.0];
+    candidates.extend(scores.windows(2).map(|pair| (pair[0] + pair[1]) / 2.0));
+    candidates.push(if inclusive { max + 1.0 } else { max });
+
+    let f1 = |threshold: f64| {
+        let (mut tp, mut fp, mut fn_) = (0, 0, 0);
+        for &(score, is_gold) in &labeled {
+            let is_break = if inclusive {
+                score >= threshold
+            } else {
+                score > threshold
+            };
+            match (is_break, is_gold) {
+                (true, true) => tp += 1,
+                (true, false) => fp += 1,
+                (false, true) => fn_ += 1,
+                (false, false) => {}
+            }
+        }
+        if tp + fp + fn_ == 0 {
+            1.0
+        } else {
+            2.0 * tp as f64 / (2 * tp + fp + fn_) as f64
+        }
+    };
+
+    let mut best = candidates[0];
+    let mut best_f1 = f1(best);
+    for &candidate in &candidates[1..] {
+        let score = f1(candidate);
+        let closer = (candidate - parser.threshold).abs() < (best - parser.threshold).abs();
+        if score > best_f1 || (score == best_f1 && closer) {
+            best = candidate;
+            best_f1 = score;
+        }
+    }
+    best
+}
+
+/// Measure how closely two segmentations of the same text agree
+///
+/// Returns the F1 score of the break positions in `b` against those in `a`:
+/// 1.0 when they match exactly and 0.0 when they share no break. Two
+/// single-chunk segmentations agree fully.
+pub fn segmentation_agreement(a: &[String], b: &[String]) -> f64 {
+    let a_breaks: HashSet<usize> = chunk_boundaries(a).into_iter().collect();
+    let b_breaks: HashSet<usize> = chunk_boundaries(b).into_iter().collect();
+    if a_breaks.is_empty() && b_breaks.is_empty() {
+        return 1.0;
+    }
+    let shared = a_breaks.intersection(&b_breaks).count();
+    2.0 * shared as f64 / (a_breaks.len() + b_breaks.len()) as f64
+}
+
+/// List the break positions where two segmentations of the same text differ
+///
+/// Returns the char offset of each break found in only one of `a` and `b`,
+/// in increasing order, paired with true when the break is in `a`.
+pub fn diff_segmentation(a: &[String], b: &[String]) -> Vec<(usize, bool)> {
+    let a_breaks: HashSet<usize> = chunk_boundaries(a).into_iter().collect();
+    let b_breaks: HashSet<usize> = chunk_boundaries(b).into_iter().collect();
+    let mut diff: Vec<(usize, bool)> = a_breaks
+        .symmetric_difference(&b_breaks)
+        .map(|&offset| (offset, a_breaks.contains(&offset)))
+        .collect();
+    diff.sort_unstable();
+    diff
+}
+
+// Helper to list the char offsets at which chunks after the first start
+fn chunk_boundaries<S: AsRef<str>>(chunks: &[S]) -> Vec<usize> {
+    let mut boundaries = Vec::new();
+    let mut offset = 0;
+    for chunk in chunks {
+        if offset > 0 {
+            boundaries.push(offset);
+        }
+        offset += chunk.as_ref().chars().count();
+    }
+    boundaries
+}
+
+/// Guess whether the text is mojibake, text decoded with the wrong encoding
+///
+/// Counts replacement characters, C1 control characters, CJK compatibility
+/// ideographs, the `縺`, `繧` and `繝` that UTF-8 kana turn into when read as
+/// Shift_JIS, and `Ã`, `Â`, `ã` or `â` followed by a Latin-1 or Windows-1252
+/// symbol, as left by UTF-8 read as Latin-1. The text is flagged when these
+/// make up at least a fifth of its non-whitespace characters. `parse` does not
+/// check this; callers can skip segmenting flagged text.
+pub fn looks_like_mojibake(sentence: &str) -> bool {
+    let chars: Vec<char> = sentence.chars().filter(|c| !c.is_whitespace()).collect();
+    if chars.is_empty() {
+        return false;
+    }
+    let mut suspicious = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if matches!(c, 'Ã' | 'Â' | 'ã' | 'â')
+            && chars.get(i + 1).is_some_and(|&c| is_latin1_symbol(c))
+        {
+            suspicious += 2;
+            i += 2;
+            continue;
+        }
+        if matches!(
+            c,
+            '\u{FFFD}' | '\u{0080}'..='\u{009F}' | '\u{F900}'..='\u{FAFF}' | '縺' | '繧' | '繝'
+        ) {
+            suspicious += 1;
+        }
+        i += 1;
+    }
+    suspicious * 5 >= chars.len()
+}
+
+/// Language guessed by [`detect_language`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    /// Japanese, written with kana and usually Han
+    Japanese,
+    /// Chinese, written with Han only
+    Chinese,
+    /// Thai
+    Thai,
+}
+
+impl Language {
+    /// Return the language code BudouX uses for the language's model
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::Japanese => "ja",
+            Language::Chinese => "zh-hans",
+            Language::Thai => "th",
+        }
+    }
+}
+
+/// Guess the language of the text from the scripts of its letters
+///
+/// Thai wins when Thai letters outnumber CJK ones. Otherwise CJK text with
+/// any hiragana or katakana is Japanese and CJK text without kana is
+/// Chinese. Text with neither script gives `None`.
+pub fn detect_language(sentence: &str) -> Option<Language> {
+    let (mut kana, mut han, mut thai) = (0, 0, 0);
+    for c in sentence.chars() {
+        match c {
+            '\u{3040}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}' | '\u{FF66}'..='\u{FF9F}' => {
+                kana += 1
+            }
+            '\u{0E00}'..='\u{0E7F}' => thai += 1,
+            _ if char_script(c) == ScriptKind::Cjk => han += 1,
+            _ => {}
+        }
+    }
+    if thai == 0 && kana + han == 0 {
+        None
+    } else if thai > kana + han {
+        Some(Language::Thai)
+    } else if kana > 0 {
+        Some(Language::Japanese)
+    } else {
+        Some(Language::Chinese)
+    }
+}
+
+/// Check whether a character is a Latin-1 or Windows-1252 symbol that
+/// follows a lead character in UTF-8 text read as Latin-1
+fn is_latin1_symbol(c: char) -> bool {
+    matches!(c, '\u{0080}'..='\u{00BF}') || "€‚ƒ„…†‡ˆ‰Š‹ŒŽ‘’“”•–—˜™š›œžŸ".contains(c)
+}
+
+/// Registry of the bundled models by language code
+static MODELS: Lazy<HashMap<&'static str, &'static Model>> = Lazy::new(|| {
+    #[allow(unused_mut)]
+    let mut models = HashMap::new();
+    #[cfg(feature = "model-ja")]
+    if let Ok(model) = japanese_model() {
+        models.insert("ja", model);
+    }
+    models
+});
+
+/// Recommended default threshold of each bundled language
+///
+/// - `ja`: 0.0, the threshold the model was trained for and the reference
+///   parser uses
+static DEFAULT_THRESHOLDS: Lazy<HashMap<&'static str, f64>> = Lazy::new(|| {
+    #[allow(unused_mut)]
+    let mut thresholds = HashMap::new();
+    #[cfg(feature = "model-ja")]
+    thresholds.insert("ja", 0.0);
+    thresholds
+});
+
+/// Return the models embedded in the binary, keyed by language code
+///
+/// Only models whose `model-*` feature is enabled are present.
+pub fn models() -> &'static HashMap<&'static str, &'static Model> {
+    &MODELS
+}
+
+/// Return the recommended default threshold of a bundled language
+///
+/// The values are: `ja` 0.0.
+pub fn default_threshold(language: &str) -> Option<f64> {
+    DEFAULT_THRESHOLDS.get(language).copied()
+}
+
+/// Load a parser with the model bundled for a language code and the
+/// language's [`default_threshold`]
+pub fn load_parser_for(language: &str) -> Result<Parser> {
+    let parser = bundled_parser(language)?;
+    Ok(match default_threshold(language) {
+        Some(threshold) => parser.with_threshold(threshold),
+        None => parser,
+    })
+}
+
+/// Load a parser with the model bundled for a language code
+fn bundled_parser(language: &str) -> Result<Parser> {
+    let model = models()
+        .get(language)
+        .ok_or_else(|| BudouXError::UnsupportedLanguage(language.to_string()))?;
+
+    Ok(Parser::new((*model).clone()).with_language(language))
+}
+
+/// Load a parser with the default Japanese model
+///
+/// # Panics
+///
+/// Panics if the embedded model cannot be parsed; use
+/// [`try_load_default_japanese_parser`] to get an error instead.
+#[cfg(feature = "model-ja")]
+pub fn load_default_japanese_parser() -> Parser {
+    try_load_default_japanese_parser().expect("Failed to parse Japanese model")
+}
+
+/// Load a parser with the default Japanese model, returning
+/// [`BudouXError::ModelLoadError`] if the embedded model cannot be parsed
+#[cfg(feature = "model-ja")]
+pub fn try_load_default_japanese_parser() -> Result<Parser> {
+    Ok(Parser::new(japanese_model()?.clone()).with_language("ja"))
+}
+
+/// Load a parser from a JSON file
+pub fn load_parser_from_file(path: &str) -> Result<Parser> {
+    let model_json =
+        std::fs::read_to_string(path).map_err(|e| BudouXError::ModelLoadError(e.to_string()))?;
+
+    Parser::from_json_str(&model_json)
+}
+
+/// Segment a text file line by line, writing the chunks joined by `separator`
+///
+/// Only one line is held in memory at a time. Line endings are copied as
+/// they are and never segmented.
+pub fn segment_file(
+    parser: &Parser,
+    input: impl AsRef<std::path::Path>,
+    output: impl AsRef<std::path::Path>,
+    separator: &str,
+) -> Result<()> {
+    use std::io::{BufRead, Write};
+
+    let (input, output) = (input.as_ref(), output.as_ref());
+    let io_error = |path: &std::path::Path, e: std::io::Error| {
+        BudouXError::IoError(format!("{}: {}", path.display(), e))
+    };
+    let file = std::fs::File::open(input).map_err(|e| io_error(input, e))?;
+    let mut reader = std::io::BufReader::new(file);
+    let file = std::fs::File::create(output).map_err(|e| io_error(output, e))?;
+    let mut writer = std::io::BufWriter::new(file);
+
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader
+            .read_line(&mut line)
+            .map_err(|e| io_error(input, e))?
+            == 0
+        {
+            break;
+        }
+        let text = line.trim_end_matches(['\r', '\n']);
+        let segmented = parser.parse_joined(text, separator);
+        writer
+            .write_all(segmented.as_bytes())
+            .and_then(|_| writer.write_all(&line.as_bytes()[text.len()..]))
+            .map_err(|e| io_error(output, e))?;
+    }
+    writer.flush().map_err(|e| io_error(output, e))
+}
+
+/// Load a parser from a JSON file and report its size, load time and entries
+pub fn load_parser_from_file_verbose(path: &str) -> Result<(Parser, LoadReport)> {
+    let model_json =
+        std::fs::read_to_string(path).map_err(|e| BudouXError::ModelLoadError(e.to_string()))?;
+
+    let start = std::time::Instant::now();
+    let parser = Parser::from_json_str(&model_json)?;
+    let report = LoadReport {
+        file_size: model_json.len() as u64,
+        parse_duration: start.elapsed(),
+        stats: parser.model.stats(),
+    };
+
+    Ok((parser, report))
+}
+
+/// Load a parser from a JSON file without blocking the async runtime
+#[cfg(feature = "tokio")]
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(path = %path.as_ref().display()))
+)]
+pub async fn load_parser_from_file_async(path: impl AsRef<std::path::Path>) -> Result<Parser> {
+    let model_json = tokio::fs::read(path)
+        .await
+        .map_err(|e| BudouXError::ModelLoadError(e.to_string()))?;
+
+    Parser::from_json_bytes(&model_json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_feature_score() {
+        let model = japanese_model().unwrap().clone();
+        assert_eq!(model.feature_score(FeatureGroup::UW3, "。"), Some(6699));
+        assert_eq!(model.feature_score(FeatureGroup::UW3, "☃"), None);
+        assert_eq!(model.feature_score(FeatureGroup::TW4, "。"), None);
+        assert_eq!(FeatureGroup::BW2.as_str(), "BW2");
+    }
+
+    #[test]
+    fn test_model_average() {
+        let a: Model = serde_json::from_str(r#"{"UW1": {"a": 100, "b": 51}}"#).unwrap();
+        let b: Model = serde_json::from_str(r#"{"UW1": {"a": 200}, "BW2": {"cd": -40}}"#).unwrap();
+        let averaged = Model::average(&[&a, &b]);
+        assert_eq!(averaged.feature_score(FeatureGroup::UW1, "a"), Some(150));
+        assert_eq!(averaged.feature_score(FeatureGroup::UW1, "b"), Some(26));
+        assert_eq!(averaged.feature_score(FeatureGroup::BW2, "cd"), Some(-20));
+        assert_eq!(averaged.stats().total(), 3);
+        assert!(Model::average(&[]).is_empty());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_japanese_parser() {
+        let parser = load_default_japanese_parser();
+        let result = parser.parse("今日は天気です。");
+        assert_eq!(result, vec!["今日は", "天気です。"]);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_empty_string() {
+        let parser = load_default_japanese_parser();
+        let result = parser.parse("");
+        assert!(result.is_empty());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_min_chunk_chars() {
+        let parser = load_default_japanese_parser();
+        assert_eq!(parser.parse("私の家"), vec!["私の", "家"]);
+        assert_eq!(parser.parse("あ「い」う"), vec!["あ", "「い」う"]);
+
+        let parser = load_default_japanese_parser().with_min_chunk_chars(2);
+        assert_eq!(parser.parse("私の家"), vec!["私の家"]);
+        assert_eq!(parser.parse("あ「い」う"), vec!["あ「い」う"]);
+        assert_eq!(
+            parser.parse("今日は天気です。"),
+            vec!["今日は", "天気です。"]
+        );
+    }
+
+    const LONG_SENTENCE: &str = "私は遅刻魔で、待ち合わせにいつも遅刻してしまいます。\
+        メールで待ち合わせ相手に一言、「ごめんね」と謝ればどうにかなると思っていました。\
+        海外ではケータイを持っていない。本日は晴天です。ｹｰﾀｲのｿﾌﾄでメールを送る。\
+        東京Towerの上から、富士山が見える・・・と言った（笑）";
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_long_sentence_regression() {
+        let parser = load_default_japanese_parser();
+        assert_eq!(
+            parser.parse(LONG_SENTENCE),
+            vec![
+                "私は",
+                "遅刻魔で、",
+                "待ち合わせに",
+                "いつも",
+                "遅刻してしまいます。",
+                "メールで",
+                "待ち合わせ相手に",
+                "一言、",
+                "「ごめんね」と",
+                "謝れば",
+                "どうにかなると",
+                "思っていました。",
+                "海外では",
+                "ケータイを",
+                "持っていない。",
+                "本日は",
+                "晴天です。",
+                "ｹｰﾀｲの",
+                "ｿﾌﾄで",
+                "メールを",
+                "送る。",
+                "東京Towerの",
+                "上から、",
+                "富士山が",
+                "見える・・・と",
+                "言った",
+                "（笑）",
+            ]
+        );
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_deterministic_across_model_loads() {
+        let load = || {
+            let model: Model = serde_json::from_str(include_str!("models/ja.json")).unwrap();
+            Parser::new(model)
+        };
+        let first = load();
+        let second = load();
+        assert_eq!(first.parse(LONG_SENTENCE), second.parse(LONG_SENTENCE));
+
+        let bits = |parser: &Parser| -> Vec<u64> {
+            parser
+                .boundary_scores(LONG_SENTENCE)
+                .iter()
+                .map(|score| score.to_bits())
+                .collect()
+        };
+        assert_eq!(bits(&first), bits(&second));
+        assert_eq!(bits(&first), bits(&load_default_japanese_parser()));
+    }
+
+    #[test]
+    fn test_from_json_bytes() {
+        let parser = Parser::from_json_bytes(include_bytes!("models/ja.json")).unwrap();
+        assert_eq!(
+            parser.parse("今日は天気です。"),
+            vec!["今日は", "天気です。"]
+        );
+
+        let err = Parser::from_json_bytes(b"{\"UW1\": {\"\xff\": 1}}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Failed to load model: model is not valid UTF-8 (invalid byte at offset 10)"
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_load_parser_from_file_async() {
+        let path = std::env::temp_dir().join(format!("budoux-async-{}.json", std::process::id()));
+        std::fs::write(&path, include_str!("models/ja.json")).unwrap();
+        let parser = load_parser_from_file_async(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            parser.parse("今日は天気です。"),
+            vec!["今日は", "天気です。"]
+        );
+
+        assert!(load_parser_from_file_async(&path).await.is_err());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_segment_file() {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("budoux-in-{}.txt", std::process::id()));
+        let output = dir.join(format!("budoux-out-{}.txt", std::process::id()));
+        std::fs::write(&input, "今日は天気です。\r\n\n私の家\n").unwrap();
+
+        let parser = load_default_japanese_parser();
+        segment_file(&parser, &input, &output, ZWSP).unwrap();
+        let segmented = std::fs::read_to_string(&output).unwrap();
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&output).unwrap();
+        assert_eq!(segmented, "今日は\u{200B}天気です。\r\n\n私の\u{200B}家\n");
+
+        let err = segment_file(&parser, &input, &output, ZWSP).unwrap_err();
+        assert!(matches!(err, BudouXError::IoError(_)));
+        assert!(err.to_string().contains("budoux-in-"));
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parser_config_round_trip() {
+        let config = ParserConfig {
+            threshold: 100.0,
+            min_chunk_chars: 2,
+            break_on_script_change: true,
+            ..ParserConfig::default()
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        let restored: ParserConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, config);
+
+        let parser = restored.build().unwrap();
+        assert_eq!(parser.parse("私の家"), vec!["私の家"]);
+        assert_eq!(parser.parse("東京Tower"), vec!["東京", "Tower"]);
+
+        let config: ParserConfig = serde_json::from_str(r#"{"language": "xx"}"#).unwrap();
+        assert!(matches!(
+            config.build(),
+            Err(BudouXError::UnsupportedLanguage(lang)) if lang == "xx"
+        ));
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_segment_with() {
+        let parser = load_default_japanese_parser();
+        let text = "今日は天気です。\n\n私の家";
+        let result: Vec<Vec<String>> = text.lines().segment_with(&parser).collect();
+        assert_eq!(
+            result,
+            vec![
+                vec!["今日は".to_string(), "天気です。".to_string()],
+                vec![],
+                vec!["私の".to_string(), "家".to_string()],
+            ]
+        );
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_all() {
+        let parser = load_default_japanese_parser();
+        let inputs = ["今日は天気です。", "私の家"];
+        let result = parser.parse_all(&inputs);
+        assert_eq!(
+            result,
+            vec![
+                (0, "今日は".to_string()),
+                (0, "天気です。".to_string()),
+                (1, "私の".to_string()),
+                (1, "家".to_string()),
+            ]
+        );
+        for (idx, input) in inputs.iter().enumerate() {
+            let count = result.iter().filter(|(i, _)| *i == idx).count();
+            assert_eq!(count, parser.parse(input).len());
+        }
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_boundary_scores() {
+        let parser = load_default_japanese_parser();
+        let scores = parser.boundary_scores("今日は天気です。");
+        assert_eq!(scores.len(), 7);
+        let breaks: Vec<usize> = (0..scores.len()).filter(|&i| scores[i] > 0.0).collect();
+        assert_eq!(breaks, vec![2]);
+        assert!(parser.boundary_scores("").is_empty());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_limited() {
+        let parser = load_default_japanese_parser();
+        let sentence = "私は遅刻魔で、待ち合わせにいつも遅刻してしまいます。";
+        assert_eq!(
+            parser.parse_limited(sentence, 2),
+            vec!["私は", "遅刻魔で、待ち合わせにいつも遅刻してしまいます。"]
+        );
+        assert_eq!(parser.parse_limited(sentence, 100), parser.parse(sentence));
+        assert!(parser.parse_limited(sentence, 0).is_empty());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_break_mask() {
+        let parser = load_default_japanese_parser();
+        let mask = parser.break_mask(LONG_SENTENCE);
+        assert_eq!(mask.len(), LONG_SENTENCE.chars().count());
+        assert!(mask[0]);
+
+        let mut chunks: Vec<String> = Vec::new();
+        for (c, &starts) in LONG_SENTENCE.chars().zip(&mask) {
+            if starts {
+                chunks.push(String::new());
+            }
+            chunks.last_mut().unwrap().push(c);
+        }
+        assert_eq!(chunks, parser.parse(LONG_SENTENCE));
+        assert!(parser.break_mask("").is_empty());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_joined() {
+        let parser = load_default_japanese_parser();
+        assert_eq!(
+            parser.parse_joined("今日は天気です。", ZWSP),
+            "今日は\u{200B}天気です。"
+        );
+        assert_eq!(parser.parse_joined("", ZWSP), "");
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_soft_hyphenated() {
+        let parser = load_default_japanese_parser();
+        assert_eq!(
+            parser.parse_soft_hyphenated("今日は天気です。"),
+            "今日は\u{00AD}天気です。"
+        );
+        assert_eq!(
+            parser
+                .parse_soft_hyphenated("私の家")
+                .matches(SOFT_HYPHEN)
+                .count(),
+            1
+        );
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_to_wbr_html() {
+        let parser = load_default_japanese_parser();
+        assert_eq!(
+            parser.to_wbr_html("今日は天気です。"),
+            "今日は<wbr>天気です。"
+        );
+        assert_eq!(parser.to_wbr_html("a<b>&\"'"), "a&lt;b&gt;&amp;&quot;&#39;");
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_has_break() {
+        let parser = load_default_japanese_parser();
+        for sentence in ["", "は", "東京Tower", "今日は天気です。", "私の家"] {
+            assert_eq!(parser.has_break(sentence), parser.parse(sentence).len() > 1);
+        }
+        assert!(!parser.has_break("東京Tower"));
+        assert!(parser.has_break("今日は天気です。"));
+        assert!(!load_default_japanese_parser()
+            .with_min_chunk_chars(2)
+            .has_break("私の家"));
+
+        let variants = [
+            parser.clone().with_field_separators(&['\t']),
+            parser.clone().with_strip_bom(true).with_threshold(-10000.0),
+            parser.clone().with_min_chunk_chars(3),
+        ];
+        for parser in variants {
+            for sentence in ["\u{FEFF}は", "東京\tTower", "今日は天気です。", "私の家"]
+            {
+                assert_eq!(parser.has_break(sentence), parser.parse(sentence).len() > 1);
+            }
+        }
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_calibrate_threshold() {
+        let parser = load_default_japanese_parser();
+        let examples: [(&str, &[&str]); 2] = [
+            ("今日は天気です。", &["今日は", "天気です。"]),
+            ("私の家", &["私の", "家"]),
+        ];
+        let threshold = calibrate_threshold(&parser, &examples);
+        let calibrated = parser.clone().with_threshold(threshold);
+        for (sentence, gold) in examples {
+            assert_eq!(calibrated.parse(sentence), gold);
+        }
+
+        let examples: [(&str, &[&str]); 2] = [
+            ("今日は天気です。", &["今日は天気です。"]),
+            ("私の家", &["私の家"]),
+        ];
+        let threshold = calibrate_threshold(&parser, &examples);
+        assert!(threshold > 0.0);
+        let calibrated = parser.clone().with_threshold(threshold);
+        assert_eq!(
+            calibrated.parse("今日は天気です。"),
+            vec!["今日は天気です。"]
+        );
+
+        // Separator boundaries always break and are no candidates
+        let separated = parser.clone().with_field_separators(&['\t']);
+        let examples: [(&str, &[&str]); 2] = [
+            ("今日は\t天気です。", &["今日は", "\t", "天気です。"]),
+            ("私の家", &["私の家"]),
+        ];
+        let threshold = calibrate_threshold(&separated, &examples);
+        assert!(threshold.is_finite());
+        let calibrated = separated.with_threshold(threshold);
+        for (sentence, gold) in examples {
+            assert_eq!(calibrated.parse(sentence), gold);
+        }
+
+        // A score equal to an inclusive threshold breaks
+        let inclusive = parser.with_inclusive_threshold(true);
+        let examples: [(&str, &[&str]); 1] = [("今日は", &["今日は"])];
+        let threshold = calibrate_threshold(&inclusive, &examples);
+        let calibrated = inclusive.with_threshold(threshold);
+        assert_eq!(calibrated.parse("今日は"), vec!["今日は"]);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_break_on_script_change() {
+        let parser = load_default_japanese_parser();
+        assert_eq!(parser.parse("東京Tower"), vec!["東京Tower"]);
+
+        let parser = parser.with_break_on_script_change(true);
+        assert_eq!(parser.parse("東京Tower"), vec!["東京", "Tower"]);
+        assert_eq!(parser.parse("Towerは高い"), vec!["Tower", "は", "高い"]);
+        assert_eq!(parser.parse("東京2020"), vec!["東京2020"]);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_kana_kinsoku() {
+        let parser = load_default_japanese_parser().with_threshold(-100000.0);
+        assert_eq!(parser.parse("待って"), vec!["待", "っ", "て"]);
+
+        let parser = parser.with_kana_kinsoku(true);
+        assert_eq!(parser.parse("待って"), vec!["待", "って"]);
+        assert_eq!(parser.parse("コーヒー"), vec!["コー", "ヒー"]);
+        assert_eq!(parser.parse("ｺｰﾋｰをﾔｯﾀ"), vec!["ｺｰ", "ﾋｰ", "を", "ﾔ", "ｯﾀ"]);
+
+        let parser = load_default_japanese_parser().with_kana_kinsoku(true);
+        for chunk in parser.parse("ちょっと待ってください。もっとコーヒーが欲しかったです。")
+        {
+            assert!(!chunk.ends_with('っ'));
+            assert!(!chunk.starts_with('ー'));
+        }
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_variation_selector_attaches() {
+        let parser = load_default_japanese_parser().with_threshold(-100000.0);
+        assert_eq!(parser.parse("葛\u{E0100}城"), vec!["葛\u{E0100}", "城"]);
+        assert_eq!(parser.parse("辻\u{FE00}堂"), vec!["辻\u{FE00}", "堂"]);
+
+        let parser = load_default_japanese_parser().with_always_break_after(&['葛']);
+        assert_eq!(parser.parse("葛\u{E0100}飾区"), vec!["葛\u{E0100}", "飾区"]);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_atomic_token_classes() {
+        let parser = load_default_japanese_parser().with_threshold(-100000.0);
+        assert_eq!(parser.parse("1,5").len(), 3);
+
+        let parser = parser.with_atomic_token_classes(&[TokenClass::Url]);
+        let url = "https://example.com/path?q=1";
+        let chunks = parser.parse(&format!("詳細は{}を見て。", url));
+        assert!(chunks.iter().any(|chunk| chunk == url));
+        let chunks = parser.parse("連絡はinfo@example.co.jpまで。");
+        assert!(chunks.iter().any(|chunk| chunk == "info@example.co.jp"));
+        let chunks = parser.parse("www.example.com.");
+        assert_eq!(chunks, vec!["www.example.com", "."]);
+        assert_eq!(parser.parse("a@b").len(), 3);
+
+        let parser = parser.with_atomic_token_classes(&[TokenClass::Number, TokenClass::AsciiWord]);
+        assert_eq!(
+            parser.parse("値は1,234.5円"),
+            vec!["値", "は", "1,234.5", "円"]
+        );
+        assert_eq!(parser.parse("1,5,"), vec!["1,5", ","]);
+        assert_eq!(parser.parse("東京Tower"), vec!["東", "京", "Tower"]);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_scores_table() {
+        let parser = load_default_japanese_parser();
+        let sentence = "今日は天気です。";
+        let table = parser.scores_table(sentence);
+        let rows: Vec<&str> = table.lines().collect();
+        assert_eq!(rows.len(), sentence.chars().count() + 1);
+        assert_eq!(rows[0], "char\tscore\tbreak");
+        assert_eq!(rows[1], "今\t\ttrue");
+        let scores = parser.boundary_scores(sentence);
+        assert_eq!(rows[4], format!("天\t{}\ttrue", scores[2]));
+        assert!(rows[5].ends_with("\tfalse"));
+
+        let table = parser.scores_table("a\tb");
+        assert!(table.lines().nth(2).unwrap().starts_with("\\t\t"));
+        assert_eq!(parser.scores_table(""), "char\tscore\tbreak\n");
+
+        let parser = load_default_japanese_parser().with_strip_bom(true);
+        let table = parser.scores_table("\u{FEFF}今日は天気です。");
+        let rows: Vec<&str> = table.lines().collect();
+        assert_eq!(rows[2], "今\t\tfalse");
+        assert_eq!(rows[5], format!("天\t{}\ttrue", scores[2]));
+
+        let parser = load_default_japanese_parser().with_field_separators(&['|']);
+        let table = parser.scores_table("今日|天気");
+        let rows: Vec<&str> = table.lines().collect();
+        assert_eq!(rows[3], "|\tinf\ttrue");
+        assert_eq!(rows[4], "天\tinf\ttrue");
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_with_threshold() {
+        let parser = load_default_japanese_parser();
+        let sentence = "今日は天気です。";
+        assert_eq!(
+            parser.parse_with_threshold(sentence, 100000.0),
+            vec![sentence]
+        );
+        assert_eq!(
+            parser.parse_with_threshold(sentence, -100000.0).len(),
+            sentence.chars().count()
+        );
+        assert_eq!(
+            parser.parse_with_threshold(sentence, 0.0),
+            parser.parse(sentence)
+        );
+        assert_eq!(parser.parse(sentence), vec!["今日は", "天気です。"]);
+    }
+
+    #[test]
+    fn test_looks_like_mojibake() {
+        assert!(looks_like_mojibake("縺薙ｓ縺ｫ縺｡縺ｯ"));
+        assert!(looks_like_mojibake("ã“ã‚“ã«ã¡ã¯"));
+        assert!(looks_like_mojibake("cafÃ©"));
+        assert!(looks_like_mojibake("今日\u{FFFD}\u{FFFD}"));
+
+        assert!(!looks_like_mojibake(LONG_SENTENCE));
+        assert!(!looks_like_mojibake("今日は天気です。"));
+        assert!(!looks_like_mojibake("São Paulo e Brasília"));
+        assert!(!looks_like_mojibake(""));
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parser_pool() {
+        let pool = ParserPool::new(load_default_japanese_parser(), 3);
+        assert_eq!(pool.size(), 3);
+        assert_eq!(ParserPool::new(Parser::new(Model::empty()), 0).size(), 1);
+
+        // A held slot is skipped, and each slot keeps its own cache
+        {
+            let mut first = pool.get();
+            first.parse("今日は天気です。");
+            let mut second = pool.get();
+            assert_eq!(second.cached(), 0);
+            second.parse("今日は天気です。");
+            assert_eq!(first.cached(), 1);
+        }
+        let cached: usize = (0..3).map(|_| pool.get().cached()).sum();
+        assert_eq!(cached, 2);
+
+        let expected = load_default_japanese_parser().parse(LONG_SENTENCE);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| pool.get().parse(LONG_SENTENCE)))
+                .collect();
+            for handle in handles {
+                assert_eq!(handle.join().unwrap(), expected);
+            }
+        });
+        assert_eq!(pool.get().parse(LONG_SENTENCE), expected);
+    }
+
+    #[test]
+    fn test_caching_parser() {
+        let mut parser = CachingParser::new(Arc::new(Parser::new(Model::empty())), 2);
+        assert_eq!(parser.parse("ab"), vec!["ab"]);
+        assert_eq!(parser.parse("ab"), vec!["ab"]);
+        assert_eq!(parser.cached(), 1);
+        parser.parse("cd");
+        assert_eq!(parser.cached(), 2);
+        parser.parse("ef");
+        assert_eq!(parser.cached(), 1);
+
+        let mut uncached = CachingParser::new(Arc::new(Parser::new(Model::empty())), 0);
+        uncached.parse("ab");
+        assert_eq!(uncached.cached(), 0);
+
+        let pool = ParserPool::new(Parser::new(Model::empty()), 2).with_cache_capacity(0);
+        pool.get().parse("ab");
+        assert_eq!(pool.get().cached() + pool.get().cached(), 0);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_annotated_breaks() {
+        let parser = load_default_japanese_parser()
+            .with_always_break_after(&['\n', '、'])
+            .with_break_before(&['「']);
+        assert_eq!(
+            parser.parse_annotated_breaks("今日は天気です。\n私は遅刻魔で、待つ「はい」"),
+            vec![
+                ("今日は".to_string(), BreakReason::Model),
+                ("天気です。".to_string(), BreakReason::Model),
+                ("\n".to_string(), BreakReason::Newline),
+                ("私は".to_string(), BreakReason::Model),
+                ("遅刻魔で、".to_string(), BreakReason::Punctuation),
+                ("待つ".to_string(), BreakReason::Forced),
+                ("「はい」".to_string(), BreakReason::End),
+            ]
+        );
+        assert!(parser.parse_annotated_breaks("").is_empty());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_tagged() {
+        let parser = load_default_japanese_parser().with_break_on_script_change(true);
+        assert_eq!(
+            parser.parse_tagged("東京Tower"),
+            vec![
+                ("東京".to_string(), ScriptKind::Cjk),
+                ("Tower".to_string(), ScriptKind::Latin),
+            ]
+        );
+        assert_eq!(ScriptKind::of("天気です。"), ScriptKind::Cjk);
+        assert_eq!(ScriptKind::of("東京Ab"), ScriptKind::Mixed);
+        assert_eq!(ScriptKind::of("2020、"), ScriptKind::Other);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_empty_model_fallback() {
+        let sentence = "今日は、天気です。「明日も？」東京Towerへ";
+        let parser = Parser::new(Model::empty());
+        assert!(Model::empty().is_empty());
+        assert_eq!(parser.parse(sentence), vec![sentence]);
+
+        let parser = parser.with_empty_model_fallback(true);
+        assert_eq!(
+            parser.parse(sentence),
+            vec!["今日は、", "天気です。", "「明日も？」東京", "Tower", "へ"]
+        );
+
+        let parser = load_default_japanese_parser().with_empty_model_fallback(true);
+        assert_eq!(
+            parser.parse("今日は天気です。"),
+            vec!["今日は", "天気です。"]
+        );
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_collect_vocab() {
+        let parser = load_default_japanese_parser();
+        let vocab = parser.collect_vocab(&["今日は天気です。", "今日は晴れ。"]);
+        assert_eq!(vocab.len(), 3);
+        for chunk in ["今日は", "天気です。", "晴れ。"] {
+            assert!(vocab.contains(chunk));
+        }
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_chunk_frequencies() {
+        let parser = load_default_japanese_parser();
+        let counts = parser.chunk_frequencies("今日は天気です。今日は晴れ。");
+        assert_eq!(counts["今日は"], 2);
+        assert_eq!(counts["天気です。"], 1);
+        assert!(parser.chunk_frequencies("").is_empty());
+
+        let counts = parser.corpus_frequencies(&[
+            "今日は天気です。今日は晴れ。",
+            "今日は天気です。",
+            "私の家",
+        ]);
+        assert_eq!(counts["今日は"], 2);
+        assert_eq!(counts["天気です。"], 2);
+        assert_eq!(counts["家"], 1);
+    }
+
+    #[test]
+    fn test_segmentation_agreement() {
+        let to_vec = |chunks: &[&str]| chunks.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        let a = to_vec(&["私は", "遅刻魔で、", "待ち合わせに"]);
+        assert_eq!(segmentation_agreement(&a, &a), 1.0);
+
+        let b = to_vec(&["私は", "遅刻魔で", "、待ち合わせに"]);
+        let agreement = segmentation_agreement(&a, &b);
+        assert!(agreement > 0.0 && agreement < 1.0);
+        assert_eq!(agreement, 0.5);
+
+        let single = to_vec(&["私の家"]);
+        assert_eq!(segmentation_agreement(&single, &single), 1.0);
+        assert_eq!(
+            segmentation_agreement(&single, &to_vec(&["私の", "家"])),
+            0.0
+        );
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_halfwidth_kana_fold() {
+        fn break_positions(chunks: &[String]) -> Vec<usize> {
+            chunks
+                .iter()
+                .scan(0, |pos, chunk| {
+                    let start = *pos;
+                    *pos += chunk.chars().count();
+                    Some(start)
+                })
+                .collect()
+        }
+
+        let full = "タとルのカ";
+        let half = "ﾀとﾙのｶ";
+        let parser = load_default_japanese_parser();
+        let expected = break_positions(&parser.parse(full));
+        assert_ne!(break_positions(&parser.parse(half)), expected);
+
+        let parser = load_default_japanese_parser().with_halfwidth_kana_fold(true);
+        let folded = parser.parse(half);
+        assert_eq!(break_positions(&folded), expected);
+        assert_eq!(folded.concat(), half);
+        assert_eq!(
+            parser.parse("ｹｰﾀｲを持っていない。"),
+            vec!["ｹｰﾀｲを", "持っていない。"]
+        );
+    }
+
+    #[cfg(all(feature = "unicode-bidi", feature = "model-ja"))]
+    #[test]
+    fn test_keep_bidi_runs() {
+        let sentence = "私はשָׁלוֹםと言った";
+        let parser = load_default_japanese_parser().with_threshold(-10000.0);
+        assert!(!parser.parse(sentence).contains(&"שָׁלוֹם".to_string()));
+
+        let parser = parser.with_keep_bidi_runs(true);
+        let result = parser.parse(sentence);
+        assert!(result.contains(&"שָׁלוֹם".to_string()));
+        assert_eq!(result.concat(), sentence);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_ranges() {
+        let parser = load_default_japanese_parser();
+        let sentence = "今日は天気です。";
+        let ranges = parser.parse_ranges(sentence);
+        assert_eq!(ranges, vec![0..9, 9..24]);
+        let chunks: Vec<&str> = ranges.iter().map(|r| &sentence[r.clone()]).collect();
+        assert_eq!(chunks, parser.parse(sentence));
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_to_count() {
+        let parser = load_default_japanese_parser();
+        let sentence = "私は遅刻魔で、待ち合わせにいつも遅刻してしまいます。";
+        assert_eq!(parser.parse(sentence).len(), 5);
+        assert_eq!(
+            parser.parse_to_count(sentence, 2),
+            vec!["私は遅刻魔で、", "待ち合わせにいつも遅刻してしまいます。"]
+        );
+        assert_eq!(parser.parse_to_count(sentence, 1), vec![sentence]);
+        assert_eq!(parser.parse_to_count(sentence, 9), parser.parse(sentence));
+        assert!(parser.parse_to_count(sentence, 0).is_empty());
+        assert!(parser.parse_to_count("", 3).is_empty());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_line_col() {
+        let parser = load_default_japanese_parser();
+        let chunks = parser.parse_line_col("今日は天気です。\n私の家");
+        let starts: Vec<(usize, usize)> = chunks.iter().map(|(start, _)| *start).collect();
+        // The newline starts the third chunk, which reports the 0-based
+        // character position of the newline itself on the first line
+        assert_eq!(chunks[2].1, "\n私の");
+        assert_eq!(starts, vec![(0, 0), (0, 3), (0, 8), (1, 2)]);
+
+        let chunks = parser.parse_line_col("今日は\n\n明日は雨です。");
+        assert_eq!(chunks.last().unwrap(), &((2, 3), "雨です。".to_string()));
+        assert!(parser.parse_line_col("").is_empty());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_halfwidth_kana_fold_composes_sound_marks() {
+        let sentence = "ﾃﾞｰﾀをﾊﾟｿｺﾝで";
+        let parser = load_default_japanese_parser()
+            .with_halfwidth_kana_fold(true)
+            .with_threshold(-10000.0);
+        let ranges = parser.parse_ranges(sentence);
+        let chunks: Vec<&str> = ranges.iter().map(|r| &sentence[r.clone()]).collect();
+        assert_eq!(chunks, vec!["ﾃﾞ", "ｰ", "ﾀ", "を", "ﾊﾟ", "ｿ", "ｺ", "ﾝ", "で"]);
+        assert_eq!(parser.boundary_scores(sentence)[0], f64::NEG_INFINITY);
+    }
+
+    #[cfg(all(feature = "unicode-normalization", feature = "model-ja"))]
+    #[test]
+    fn test_nfkc_ranges() {
+        let sentence = "㍻元年に、ﾃﾞｰﾀを㌔で";
+        let parser = load_default_japanese_parser()
+            .with_nfkc(true)
+            .with_threshold(-10000.0);
+        let ranges = parser.parse_ranges(sentence);
+        let chunks: Vec<&str> = ranges.iter().map(|r| &sentence[r.clone()]).collect();
+        assert_eq!(chunks.concat(), sentence);
+        assert_eq!(chunks[0], "㍻");
+        assert!(chunks.contains(&"ﾃﾞ"));
+        assert!(chunks.contains(&"㌔"));
+        assert_eq!(chunks, parser.parse(sentence));
+
+        let parser = load_default_japanese_parser().with_nfkc(true);
+        let ranges = parser.parse_ranges(sentence);
+        assert_eq!(ranges.last().unwrap().end, sentence.len());
+        for range in ranges {
+            assert!(sentence.is_char_boundary(range.start));
+        }
+    }
+
+    #[cfg(all(feature = "textwrap", feature = "model-ja"))]
+    #[test]
+    fn test_textwrap_breaks_at_chunks() {
+        let parser = load_default_japanese_parser();
+        let text = "私は遅刻魔で、待ち合わせにいつも遅刻してしまいます。";
+        let chunks = parser.parse(text);
+        let lines = parser.wrap(text, 16);
+        assert_eq!(
+            lines,
+            vec![
+                "私は遅刻魔で、",
+                "待ち合わせに",
+                "いつも",
+                "遅刻してしまいます。"
+            ]
+        );
+
+        let mut boundaries = Vec::new();
+        let mut offset = 0;
+        for chunk in &chunks {
+            offset += chunk.len();
+            boundaries.push(offset);
+        }
+        let mut offset = 0;
+        for line in &lines {
+            offset += line.len();
+            assert!(boundaries.contains(&offset));
+        }
+        assert_eq!(parser.fill(text, 16), lines.join("\n"));
+    }
+
+    #[cfg(all(feature = "textwrap", feature = "model-ja"))]
+    #[test]
+    fn test_wrap_to_lines() {
+        let parser = load_default_japanese_parser();
+        let text = "私は遅刻魔で、待ち合わせにいつも遅刻してしまいます。";
+        let lines = parser.wrap_to_lines(text, 16);
+        assert_eq!(
+            lines,
+            vec![
+                "私は遅刻魔で、",
+                "待ち合わせに",
+                "いつも",
+                "遅刻してしまいます。"
+            ]
+        );
+        for line in &lines[..3] {
+            assert!(textwrap::core::display_width(line) <= 16);
+        }
+        // The last chunk is 20 columns wide and gets a line of its own
+        assert_eq!(textwrap::core::display_width(&lines[3]), 20);
+        assert_eq!(lines.concat(), text);
+
+        let lines = parser.wrap_to_lines("今日は 天気です。\n\n東京Tower", 8);
+        assert_eq!(lines, vec!["今日は", "天気です。", "", "東京Tower"]);
+        assert!(lines.iter().all(|line| line.trim_end() == line));
+        assert_eq!(parser.wrap_to_lines("", 8), vec![""]);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_break_byte_positions() {
+        let parser = load_default_japanese_parser();
+        assert_eq!(parser.break_byte_positions("今日は天気です。"), vec![9]);
+        assert!(parser.break_byte_positions("").is_empty());
+
+        let mut marked = LONG_SENTENCE.to_string();
+        for pos in parser.break_byte_positions(LONG_SENTENCE).into_iter().rev() {
+            marked.insert_str(pos, ZWSP);
+        }
+        assert_eq!(marked, parser.parse_joined(LONG_SENTENCE, ZWSP));
+
+        let parser = parser.with_strip_bom(true);
+        let sentence = "\u{FEFF}今日は天気です。";
+        assert_eq!(parser.break_byte_positions(sentence), vec![12]);
+        let mut marked = sentence.to_string();
+        marked.insert_str(12, ZWSP);
+        assert_eq!(
+            marked,
+            format!("\u{FEFF}{}", parser.parse_joined(sentence, ZWSP))
+        );
+    }
+
+    #[test]
+    fn test_inclusive_threshold() {
+        let mut model = Model::empty();
+        model.uw4.insert("b".to_string(), 1);
+        model.uw4.insert("z".to_string(), 1);
+        let parser = Parser::new(model);
+        assert_eq!(parser.boundary_scores("ab"), vec![0.0]);
+        assert_eq!(parser.parse("ab"), vec!["ab"]);
+        let parser = parser.with_inclusive_threshold(true);
+        assert_eq!(parser.parse("ab"), vec!["a", "b"]);
+        assert_eq!(parser.parse("ac"), vec!["ac"]);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_segment_result() {
+        let parser = load_default_japanese_parser();
+        let result = parser.segment_result("今日は天気です。");
+        assert_eq!(result.chunks, vec!["今日は", "天気です。"]);
+        let json: serde_json::Value = serde_json::to_value(&result).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "chunks": ["今日は", "天気です。"],
+                "count": 2,
+                "char_len": 8,
+                "lang": "ja"
+            })
+        );
+        assert_eq!(Parser::new(Model::empty()).segment_result("").lang, None);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_info() {
+        let parser = load_default_japanese_parser();
+        let info = parser.parse_info("今日は天気です。");
+        let json: serde_json::Value = serde_json::to_value(&info).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"text": "今日は", "char_start": 0, "byte_start": 0},
+                {"text": "天気です。", "char_start": 3, "byte_start": 9}
+            ])
+        );
+        assert!(parser.parse_info("").is_empty());
+
+        let info = parser
+            .with_strip_bom(true)
+            .parse_info("\u{FEFF}今日は天気です。");
+        assert_eq!((info[0].char_start, info[0].byte_start), (1, 3));
+        assert_eq!((info[1].char_start, info[1].byte_start), (4, 12));
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_strip_bom() {
+        let sentence = "\u{FEFF}今日は天気です。";
+        let parser = load_default_japanese_parser();
+        assert_eq!(parser.parse(sentence)[0], "\u{FEFF}");
+
+        let parser = parser.with_strip_bom(true);
+        assert_eq!(parser.parse(sentence), vec!["今日は", "天気です。"]);
+        assert_eq!(parser.parse_ranges(sentence), vec![3..12, 12..27]);
+        assert_eq!(parser.break_byte_positions(sentence), vec![12]);
+        let mask = parser.break_mask(sentence);
+        assert_eq!(mask.len(), sentence.chars().count());
+        assert_eq!(&mask[..5], &[true, false, false, false, true]);
+        assert!(parser.parse("\u{FEFF}").is_empty());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_keep_separators() {
+        let parser = load_default_japanese_parser();
+        let sentence = "  今日は 天気です。\t 明日は\u{3000}晴れ hello world \n";
+        let segments = parser.parse_keep_separators(sentence);
+        let restored: String = segments.iter().map(Segment::as_str).collect();
+        assert_eq!(restored, sentence);
+        assert_eq!(segments[0], Segment::Whitespace("  ".to_string()));
+        assert_eq!(segments[1], Segment::Chunk("今日は".to_string()));
+        assert_eq!(segments[2], Segment::Whitespace(" ".to_string()));
+        assert!(segments
+            .windows(2)
+            .all(|pair| !matches!(pair, [Segment::Whitespace(_), Segment::Whitespace(_)])));
+        assert_eq!(
+            parser.parse_keep_separators(" "),
+            vec![Segment::Whitespace(" ".to_string())]
+        );
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_from_json_str() {
+        let parser = Parser::from_json_str(include_str!("models/ja.json")).unwrap();
+        let default = load_default_japanese_parser();
+        assert_eq!(parser.parse(LONG_SENTENCE), default.parse(LONG_SENTENCE));
+        assert_eq!(
+            parser.boundary_scores(LONG_SENTENCE),
+            default.boundary_scores(LONG_SENTENCE)
+        );
+
+        let json = include_str!("models/ja.json").replacen("\"BW1\":{", "\"BW1\":{\"abc\":1,", 1);
+        assert!(matches!(
+            Parser::from_json_str(&json),
+            Err(BudouXError::ModelLoadError(msg)) if msg.contains("BW1")
+        ));
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_char_chunk_ids() {
+        let parser = load_default_japanese_parser();
+        assert_eq!(
+            parser.char_chunk_ids("今日は天気です。"),
+            vec![0, 0, 0, 1, 1, 1, 1, 1]
+        );
+        let ids = parser.char_chunk_ids(LONG_SENTENCE);
+        assert_eq!(ids.len(), LONG_SENTENCE.chars().count());
+        assert_eq!(ids[0], 0);
+        assert!(ids.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(*ids.last().unwrap(), parser.parse(LONG_SENTENCE).len() - 1);
+        assert!(parser.char_chunk_ids("").is_empty());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_always_break_after() {
+        let sentence = "はい。いいえ。そう";
+        let parser = load_default_japanese_parser().with_threshold(10000.0);
+        assert_eq!(parser.parse(sentence), vec![sentence]);
+
+        let parser = parser.with_always_break_after(&['。']);
+        assert_eq!(parser.parse(sentence), vec!["はい。", "いいえ。", "そう"]);
+        assert_eq!(parser.parse("はい。"), vec!["はい。"]);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_shortest_chunk() {
+        let parser = load_default_japanese_parser();
+        let chunks = parser.parse(LONG_SENTENCE);
+        let (index, chunk) = parser.shortest_chunk(LONG_SENTENCE).unwrap();
+        let min = chunks.iter().map(|chunk| chunk_len(chunk)).min().unwrap();
+        assert_eq!(chunk_len(&chunk), min);
+        let start: String = LONG_SENTENCE.chars().skip(index).take(min).collect();
+        assert_eq!(start, chunk);
+        assert_eq!(
+            parser.shortest_chunk("今日は天気です。"),
+            Some((0, "今日は".to_string()))
+        );
+        assert_eq!(parser.shortest_chunk(""), None);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_longest_chunk() {
+        let parser = load_default_japanese_parser();
+        let expected = parser
+            .parse(LONG_SENTENCE)
+            .into_iter()
+            .max_by_key(|chunk| chunk.chars().count());
+        assert_eq!(parser.longest_chunk(LONG_SENTENCE), expected);
+        assert_eq!(
+            parser.longest_chunk("今日は天気です。"),
+            Some("天気です。".to_string())
+        );
+        assert_eq!(parser.longest_chunk(""), None);
+
+        let parser = parser.with_threshold(-10000.0);
+        assert_eq!(parser.longest_chunk("今日"), Some("今".to_string()));
+        let parser = parser.with_min_chunk_chars(2);
+        assert_eq!(parser.longest_chunk("今日は"), Some("今日は".to_string()));
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_bundled_parser() {
+        let parser = bundled_parser("ja").unwrap();
+        assert_eq!(parser.language(), Some("ja"));
+        assert_eq!(
+            parser.parse("今日は天気です。"),
+            vec!["今日は", "天気です。"]
+        );
+        assert!(matches!(
+            bundled_parser("zh-hans"),
+            Err(BudouXError::UnsupportedLanguage(_))
+        ));
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_for_each_chunk() {
+        let parser = load_default_japanese_parser().with_min_chunk_chars(3);
+        for text in ["今日は天気です。", "私はその人を常に先生と呼んでいた。", ""]
+        {
+            let mut chunks = Vec::new();
+            parser.for_each_chunk(text, |chunk| chunks.push(chunk.to_string()));
+            assert_eq!(chunks, parser.parse(text));
+        }
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_boundary_entropy() {
+        let parser = load_default_japanese_parser().with_threshold(-100000.0);
+        assert_eq!(parser.boundary_entropy("今日は天気です。"), 0.0);
+        assert_eq!(parser.boundary_entropy(""), 0.0);
+
+        // Every score of an empty model sits at the threshold
+        let parser = Parser::new(Model::default());
+        assert!((parser.boundary_entropy("あいう") - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_parser_options() {
+        let parser = Parser::new(Model::default());
+        assert_eq!(parser.threshold(), 0.0);
+        assert_eq!(parser.options(), &ParserOptions::default());
+        assert!(parser.options().base_score);
+
+        let parser = parser
+            .with_threshold(-1.5)
+            .with_strip_bom(true)
+            .with_kana_kinsoku(true);
+        assert_eq!(parser.threshold(), -1.5);
+        assert!(parser.options().strip_bom);
+        assert!(parser.options().kana_kinsoku);
+        assert!(!parser.options().inclusive_threshold);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_try_load_default_japanese_parser() {
+        let parser = try_load_default_japanese_parser().unwrap();
+        assert_eq!(
+            parser.parse("今日は天気です。"),
+            vec!["今日は", "天気です。"]
+        );
+        assert_eq!(parser.language(), Some("ja"));
+
+        let corrupt = &include_str!("models/ja.json")[..100];
+        assert!(parse_embedded_model(corrupt).is_err());
+    }
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(
+            detect_language("きょうはいいてんきです。"),
+            Some(Language::Japanese)
+        );
+        assert_eq!(
+            detect_language("今日は天気です。"),
+            Some(Language::Japanese)
+        );
+        assert_eq!(detect_language("วันนี้อากาศดีมาก"), Some(Language::Thai));
+        assert_eq!(detect_language("今天天气很好。"), Some(Language::Chinese));
+        assert_eq!(detect_language("Hello, world!"), None);
+        assert_eq!(Language::Thai.code(), "th");
+    }
+
+    #[test]
+    fn test_diff_segmentation() {
+        let a = vec!["今日は".to_string(), "天気です。".to_string()];
+        let b = vec!["今日は天".to_string(), "気です。".to_string()];
+        assert_eq!(diff_segmentation(&a, &b), vec![(3, true), (4, false)]);
+        assert!(diff_segmentation(&a, &a).is_empty());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_load_parser_for() {
+        for &language in models().keys() {
+            let threshold = default_threshold(language).unwrap();
+            let parser = load_parser_for(language).unwrap();
+            assert_eq!(parser.threshold, threshold);
+            assert_eq!(parser.language(), Some(language));
+        }
+        assert_eq!(default_threshold("ja"), Some(0.0));
+        assert_eq!(default_threshold("xx"), None);
+        assert!(matches!(
+            load_parser_for("xx"),
+            Err(BudouXError::UnsupportedLanguage(_))
+        ));
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_bytes() {
+        let parser = load_default_japanese_parser();
+        let sentence = "今日は天気です。";
+        assert_eq!(
+            parser.parse_bytes(sentence.as_bytes()).unwrap(),
+            parser.parse(sentence)
+        );
+
+        let mut bytes = sentence.as_bytes().to_vec();
+        bytes.insert(3, 0xFF);
+        let err = parser.parse_bytes(&bytes).unwrap_err();
+        assert!(matches!(err, BudouXError::InvalidInput(_)));
+        assert!(err.to_string().contains("offset 3"));
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_lossy() {
+        let parser = load_default_japanese_parser();
+        let sentence = "今日は天気です。";
+        assert_eq!(
+            parser.parse_lossy(sentence.as_bytes()),
+            parser.parse(sentence)
+        );
+
+        let mut bytes = sentence.as_bytes().to_vec();
+        bytes.insert(9, 0xFF);
+        bytes.truncate(bytes.len() - 1);
+        let chunks = parser.parse_lossy(&bytes);
+        assert_eq!(chunks.concat(), "今日は\u{FFFD}天気です\u{FFFD}");
+
+        assert!(parser.parse_lossy(&[]).is_empty());
+        assert_eq!(
+            parser.parse_lossy(&[0xC0, 0x80, 0xFE]).concat(),
+            "\u{FFFD}".repeat(3)
+        );
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_effective_base_score() {
+        let parser = load_default_japanese_parser();
+        let base_score = parser.effective_base_score();
+        assert_eq!(base_score, -parser.calculate_base_score() * 0.5);
+        assert!(base_score < 0.0);
+
+        let parser = parser.with_base_scale(0.0);
+        assert_eq!(parser.effective_base_score(), 0.0);
+        assert!(
+            parser.parse(LONG_SENTENCE).len()
+                > load_default_japanese_parser().parse(LONG_SENTENCE).len()
+        );
+
+        let mut model = Model::empty();
+        model.uw4.insert("b".to_string(), 4);
+        let parser = Parser::new(model).with_base_scale(0.25);
+        assert_eq!(parser.effective_base_score(), -1.0);
+        assert_eq!(parser.boundary_scores("ab"), vec![3.0]);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_base_score() {
+        let default = load_default_japanese_parser();
+        let parser = load_default_japanese_parser().with_base_score(false);
+        assert_eq!(parser.effective_base_score(), 0.0);
+        assert!(parser.parse(LONG_SENTENCE).len() > default.parse(LONG_SENTENCE).len());
+
+        let parser = parser.with_base_scale(0.25).with_base_score(true);
+        assert_eq!(
+            parser.effective_base_score(),
+            default.effective_base_score() / 2.0
+        );
+        let parser = parser.with_base_scale(0.5);
+        assert_eq!(parser.parse(LONG_SENTENCE), default.parse(LONG_SENTENCE));
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_whitespace_only() {
+        let parser = load_default_japanese_parser().with_threshold(-10000.0);
+        for sentence in ["   ", "\t\t", "\u{3000}\u{3000}", " \t\u{3000}\n"] {
+            assert_eq!(parser.parse(sentence), vec![sentence]);
+            assert!(!parser.has_break(sentence));
+            assert_eq!(parser.longest_chunk(sentence).as_deref(), Some(sentence));
+        }
+        // Whitespace within other text is split by score
+        assert_eq!(
+            parser.parse("私 \u{3000}の"),
+            vec!["私", " ", "\u{3000}", "の"]
+        );
+
+        // Only separators split whitespace-only input, not forced breaks
+        let separated = parser.clone().with_field_separators(&['\t']);
+        assert_eq!(separated.parse("\t\t"), vec!["\t", "\t"]);
+        assert_eq!(separated.parse("\t  \t"), vec!["\t", "  ", "\t"]);
+        assert_eq!(separated.parse("a\t\t"), vec!["a", "\t", "\t"]);
+        let forced = parser.clone().with_always_break_after(&[' ']);
+        assert_eq!(forced.parse("  "), vec!["  "]);
+        assert_eq!(forced.parse("  a"), vec![" ", " ", "a"]);
+        assert_eq!(
+            forced
+                .with_break_before(&['\u{3000}'])
+                .parse("\u{3000}\u{3000}"),
+            vec!["\u{3000}\u{3000}"]
+        );
+
+        let (chunks, scores) = parser.parse_with_scores("   ");
+        assert_eq!(chunks, vec!["   "]);
+        assert_eq!(scores, parser.boundary_scores("   "));
+        assert_eq!(scores.len(), 2);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_max_bytes() {
+        let parser = load_default_japanese_parser();
+        let chunks = parser.parse_max_bytes(LONG_SENTENCE, 7);
+        assert_eq!(chunks.concat(), LONG_SENTENCE);
+        assert!(chunks
+            .iter()
+            .all(|chunk| !chunk.is_empty() && chunk.len() <= 7));
+
+        assert_eq!(
+            parser.parse_max_bytes("今日は天気です。", 7),
+            vec!["今日", "は", "天気", "です", "。"]
+        );
+        assert_eq!(
+            parser.parse_max_bytes("今日は天気です。", 100),
+            parser.parse("今日は天気です。")
+        );
+        assert_eq!(parser.parse_max_bytes("今日", 1), vec!["今", "日"]);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_try_parse() {
+        let parser = load_default_japanese_parser();
+        assert!(matches!(parser.try_parse(""), Err(BudouXError::EmptyInput)));
+        assert!(parser.parse("").is_empty());
+        assert_eq!(
+            parser.try_parse("今日は天気です。").unwrap(),
+            vec!["今日は", "天気です。"]
+        );
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_models() {
+        let model = models().get("ja").expect("ja model is bundled");
+        assert!(!model.is_empty());
+        assert!(model.validate().is_ok());
+        assert!(!models().contains_key("xx"));
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_prefix() {
+        let parser = load_default_japanese_parser();
+        let (chunks, pending) = parser.parse_prefix("今日は天気");
+        assert!(chunks.is_empty());
+        assert_eq!(pending, "今日は天気");
+
+        let (chunks, pending) = parser.parse_prefix("今日は天気で");
+        assert_eq!(chunks, vec!["今日は"]);
+        assert_eq!(pending, "天気で");
+
+        let (chunks, pending) = parser.parse_prefix(LONG_SENTENCE);
+        assert_eq!(chunks.concat() + &pending, LONG_SENTENCE);
+        let full = parser.parse(LONG_SENTENCE);
+        assert_eq!(chunks, full[..chunks.len()]);
+        assert_eq!(parser.parse_prefix(""), (Vec::new(), String::new()));
+
+        let parser = load_default_japanese_parser().with_strip_bom(true);
+        let (chunks, pending) = parser.parse_prefix("\u{FEFF}今日は天気");
+        assert!(chunks.is_empty());
+        assert_eq!(pending, "今日は天気");
+
+        // Chunks returned as complete never change as more text arrives
+        for parser in [
+            load_default_japanese_parser(),
+            load_default_japanese_parser().with_min_chunk_chars(4),
+            load_default_japanese_parser().with_strip_bom(true),
+        ] {
+            for text in [
+                LONG_SENTENCE,
+                "雨が降っているので、傘を持って行きましょう。",
+            ] {
+                let sentence = format!("\u{FEFF}{}", text);
+                let full = parser.parse(&sentence);
+                let ends = sentence.char_indices().map(|(i, _)| i).skip(1);
+                for end in ends.chain([sentence.len()]) {
+                    let (chunks, _) = parser.parse_prefix(&sentence[..end]);
+                    assert_eq!(chunks, full[..chunks.len()], "prefix: {}", &sentence[..end]);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_break_density() {
+        let parser = load_default_japanese_parser();
+        assert_eq!(parser.break_density("今日は天気です。"), 1.0 / 8.0);
+        assert_eq!(parser.break_density(""), 0.0);
+        assert_eq!(parser.break_density("今"), 0.0);
+
+        let parser = parser.with_strip_bom(true);
+        assert_eq!(parser.break_density("\u{FEFF}今日は天気です。"), 1.0 / 8.0);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_set_feature_score() {
+        let mut parser = load_default_japanese_parser();
+        let base_score = parser.effective_base_score();
+        assert!(parser.break_mask("今日は天気です。")[3]);
+
+        let old = parser
+            .model
+            .feature_score(FeatureGroup::BW2, "は天")
+            .unwrap_or(0);
+        parser.set_feature_score(FeatureGroup::BW2, "は天", -100000);
+        assert!(!parser.break_mask("今日は天気です。")[3]);
+        assert_eq!(
+            parser.effective_base_score(),
+            base_score - f64::from(-100000 - old) * 0.5
+        );
+        assert_eq!(
+            parser.effective_base_score(),
+            -parser.calculate_base_score() * 0.5
+        );
+    }
+
+    #[test]
+    fn test_set_feature_score_large() {
+        let mut parser = Parser::new(Model::empty());
+        parser.set_feature_score(FeatureGroup::UW1, "a", i32::MAX);
+        parser.set_feature_score(FeatureGroup::TW4, "abc", i32::MAX);
+        assert_eq!(
+            parser.effective_base_score(),
+            -f64::from(i32::MAX) * 2.0 * 0.5
+        );
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_prune_features() {
+        let mut model = Model::empty();
+        model.uw1.extend([
+            ("a".to_string(), 5),
+            ("b".to_string(), -50),
+            ("c".to_string(), 100),
+        ]);
+        assert_eq!(model.prune(50), 1);
+        assert_eq!(model.feature_score(FeatureGroup::UW1, "a"), None);
+        assert_eq!(model.feature_score(FeatureGroup::UW1, "b"), Some(-50));
+
+        let default = load_default_japanese_parser();
+        let mut parser = load_default_japanese_parser();
+        let entries = parser.model.stats().total();
+        let removed = parser.prune_features(100);
+        assert!(removed > 0);
+        assert_eq!(parser.model.stats().total(), entries - removed);
+        assert_eq!(
+            parser.effective_base_score(),
+            -parser.calculate_base_score() * 0.5
+        );
+        let agreement =
+            segmentation_agreement(&default.parse(LONG_SENTENCE), &parser.parse(LONG_SENTENCE));
+        assert!(agreement > 0.9, "agreement {}", agreement);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_truncate() {
+        let parser = load_default_japanese_parser();
+        assert_eq!(
+            parser.truncate("今日は天気です。", 8, "…"),
+            "今日は天気です。"
+        );
+        assert_eq!(parser.truncate("今日は天気です。", 7, "…"), "今日は…");
+        assert_eq!(parser.truncate("今日は天気です。", 2, "…"), "…");
+        assert_eq!(parser.truncate("", 0, "…"), "");
+
+        let truncated = parser.truncate(LONG_SENTENCE, 20, "…");
+        let kept = truncated.strip_suffix('…').unwrap();
+        assert!(kept.chars().count() <= 20);
+        assert!(parser
+            .parse_ranges(LONG_SENTENCE)
+            .iter()
+            .any(|r| r.end == kept.len()));
+    }
+
+    // Parsers whose boundaries depend on characters beyond the usual
+    // lookahead, each with a text on which a shorter lookahead goes wrong
+    fn late_context_cases() -> Vec<(Parser, &'static str)> {
+        // Breaks before イ unless the next two keys complete イウグ
+        let mut model = Model::empty();
+        model.uw4.insert("イ".to_string(), 1000);
+        model.tw4.insert("イウグ".to_string(), -100000);
+        let folded = Parser::new(model)
+            .with_base_score(false)
+            .with_halfwidth_kana_fold(true);
+
+        let breaks_everywhere = Parser::new(Model::empty()).with_threshold(-1.0);
+        let atomic = breaks_everywhere.with_atomic_token_classes(&[
+            TokenClass::AsciiWord,
+            TokenClass::Number,
+            TokenClass::Url,
+        ]);
+
+        #[allow(unused_mut)]
+        let mut cases = vec![
+            (folded, "ｱｲｳｸﾞｱｲｳｸｱ"),
+            (atomic.clone(), "見てwww.example.comを"),
+            (atomic.clone(), "価格は1,980円"),
+            (atomic, "連絡先はa@b.jpです"),
+        ];
+        #[cfg(feature = "unicode-normalization")]
+        {
+            let mut model = Model::empty();
+            model.uw4.insert("い".to_string(), 1000);
+            model.tw4.insert("いうぐ".to_string(), -100000);
+            let parser = Parser::new(model).with_base_score(false);
+            cases.push((parser.clone().with_nfc(true), "あいうく\u{3099}あいうくあ"));
+            cases.push((parser.with_nfkc(true), "あいうく\u{3099}あいうくあ"));
+        }
+        cases
+    }
+
+    #[test]
+    fn test_parse_prefix_late_context() {
+        for (parser, sentence) in late_context_cases() {
+            let full = parser.parse(sentence);
+            let ends = sentence.char_indices().map(|(i, _)| i).skip(1);
+            for end in ends.chain([sentence.len()]) {
+                let (chunks, pending) = parser.parse_prefix(&sentence[..end]);
+                assert_eq!(chunks, full[..chunks.len()], "prefix: {}", &sentence[..end]);
+                assert_eq!(chunks.concat() + &pending, &sentence[..end]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_edge_windows_are_skipped() {
+        let mut model = Model::empty();
+        for group in [FeatureGroup::UW1, FeatureGroup::UW6, FeatureGroup::BW1] {
+            model.feature_mut(group).insert("\0".to_string(), 1);
+            model.feature_mut(group).insert(" ".to_string(), 1);
+        }
+        model.tw4.insert("ab\0".to_string(), 1);
+        let parser = Parser::new(model);
+        let base_score = parser.effective_base_score();
+        assert_eq!(parser.boundary_scores("ab"), vec![base_score]);
+        assert_eq!(parser.boundary_scores("abc"), vec![base_score; 2]);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_bundled_model_has_no_sentinel_keys() {
+        let model = japanese_model().unwrap();
+        for group in FeatureGroup::ALL {
+            assert!(model
+                .feature(group)
+                .keys()
+                .all(|key| !key.chars().any(char::is_control)));
+        }
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_sentences() {
+        let parser = load_default_japanese_parser();
+        assert_eq!(
+            parser.parse_sentences("今日は天気です。明日は晴れるでしょうか？"),
+            vec![
+                parser.parse("今日は天気です。"),
+                parser.parse("明日は晴れるでしょうか？"),
+            ]
+        );
+        assert_eq!(
+            parser.parse_sentences("今日は天気です。"),
+            vec![vec!["今日は", "天気です。"]]
+        );
+        assert_eq!(parser.parse_sentences("今日は")[0], vec!["今日は"]);
+        assert!(parser.parse_sentences("").is_empty());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_detached_terminators() {
+        let text = "今日は天気です。「晴れ？」";
+        let parser = load_default_japanese_parser();
+        assert_eq!(
+            parser.parse_sentences(text)[0],
+            vec!["今日は", "天気です。"]
+        );
+
+        let parser = parser.with_detached_terminators(true);
+        let sentences = parser.parse_sentences(text);
+        assert_eq!(sentences[0], vec!["今日は", "天気です", "。"]);
+        assert_eq!(sentences[1].last().unwrap(), "？」");
+        assert_eq!(sentences.concat().concat(), text);
+        assert_eq!(parser.parse_sentences("。"), vec![vec!["。"]]);
+    }
+
+    #[cfg(feature = "model-ja")]
+    // Score every boundary with one freshly allocated key per lookup
+    fn allocating_scores(parser: &Parser, sentence: &str) -> Vec<f64> {
+        let chars: Vec<char> = sentence.chars().collect();
+        let window = |start: usize, len: usize| -> Option<String> {
+            let end = start.checked_add(len)?;
+            (end <= chars.len()).then(|| chars[start..end].iter().collect())
+        };
+        let base_score = parser.effective_base_score();
+        (1..chars.len())
+            .map(|i| {
+                let lookups = [
+                    (FeatureGroup::UW1, i.checked_sub(3), 1),
+                    (FeatureGroup::UW2, i.checked_sub(2), 1),
+                    (FeatureGroup::UW3, Some(i - 1), 1),
+                    (FeatureGroup::UW4, Some(i), 1),
+                    (FeatureGroup::UW5, Some(i + 1), 1),
+                    (FeatureGroup::UW6, Some(i + 2), 1),
+                    (FeatureGroup::BW1, i.checked_sub(2), 2),
+                    (FeatureGroup::BW2, Some(i - 1), 2),
+                    (FeatureGroup::BW3, Some(i), 2),
+                    (FeatureGroup::TW1, i.checked_sub(3), 3),
+                    (FeatureGroup::TW2, i.checked_sub(2), 3),
+                    (FeatureGroup::TW3, Some(i - 1), 3),
+                    (FeatureGroup::TW4, Some(i), 3),
+                ];
+                lookups
+                    .into_iter()
+                    .filter_map(|(group, start, len)| {
+                        let key = window(start?, len)?;
+                        parser.model.feature_score(group, &key)
+                    })
+                    .fold(base_score, |sum, score| sum + f64::from(score))
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_scores_match_allocating_lookup() {
+        let parser = load_default_japanese_parser();
+        assert_eq!(
+            parser.boundary_scores(LONG_SENTENCE),
+            allocating_scores(&parser, LONG_SENTENCE)
+        );
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_with_scores() {
+        for parser in [
+            load_default_japanese_parser(),
+            load_default_japanese_parser().with_halfwidth_kana_fold(true),
+            load_default_japanese_parser().with_strip_bom(true),
+            load_default_japanese_parser().with_field_separators(&['\t', '|']),
+        ] {
+            for sentence in [
+                LONG_SENTENCE,
+                "ﾃﾞｰﾀを読む",
+                "今",
+                "",
+                "\u{FEFF}今日は天気です。",
+                "今日は\t天気です。|",
+                "\t|今日は",
+            ] {
+                let (chunks, scores) = parser.parse_with_scores(sentence);
+                assert_eq!(chunks, parser.parse(sentence));
+                assert_eq!(scores, parser.boundary_scores(sentence));
+            }
+        }
+    }
+
+    #[cfg(all(feature = "smol_str", feature = "model-ja"))]
+    #[test]
+    fn test_parse_smolstr() {
+        let parser = load_default_japanese_parser();
+        let chunks = parser.parse_smolstr(LONG_SENTENCE);
+        assert_eq!(chunks, parser.parse(LONG_SENTENCE));
+        // Chunks of up to 23 bytes (seven kana or kanji) stay inline
+        for chunk in &chunks {
+            assert_eq!(chunk.is_heap_allocated(), chunk.len() > 23);
+        }
+        assert!(chunks.iter().any(|chunk| !chunk.is_heap_allocated()));
+
+        let long = parser
+            .with_threshold(10000.0)
+            .parse_smolstr("今日は天気です。");
+        assert!(long[0].is_heap_allocated());
+    }
+
+    #[cfg(all(feature = "unicode-normalization", feature = "model-ja"))]
+    #[test]
+    fn test_nfc_decomposed_input() {
+        let composed = "でも、ぎりぎりで間に合ったデータベースです";
+        let decomposed = "て\u{3099}も、き\u{3099}りき\u{3099}りて\u{3099}間に合ったテ\u{3099}ータヘ\u{3099}ースて\u{3099}す";
+        let parser = load_default_japanese_parser();
+        assert_ne!(parser.parse(decomposed).len(), parser.parse(composed).len());
+
+        let parser = parser.with_nfc(true);
+        let chunks = parser.parse(decomposed);
+        assert_eq!(chunks.concat(), decomposed);
+        let recomposed: Vec<String> = chunks
+            .iter()
+            .map(|chunk| {
+                use unicode_normalization::UnicodeNormalization;
+                chunk.nfc().collect()
+            })
+            .collect();
+        assert_eq!(recomposed, parser.parse(composed));
+        assert_eq!(
+            recomposed,
+            vec!["でも、", "ぎりぎりで", "間に", "合った", "データベースです"]
+        );
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_with_forced_breaks() {
+        let parser = load_default_japanese_parser();
+        let sentence = "今日は天気です。";
+        assert_eq!(
+            parser.parse_with_forced_breaks(sentence, &[5]).unwrap(),
+            vec!["今日は", "天気", "です。"]
+        );
+        assert_eq!(
+            parser
+                .parse_with_forced_breaks(sentence, &[0, 3, 8])
+                .unwrap(),
+            parser.parse(sentence)
+        );
+        assert!(parser
+            .parse_with_forced_breaks("", &[0])
+            .unwrap()
+            .is_empty());
+
+        let err = parser
+            .parse_with_forced_breaks("今日は", &[1, 4])
+            .unwrap_err();
+        assert!(matches!(err, BudouXError::InvalidInput(msg) if msg.contains("index 4")));
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_repeated_characters() {
+        let parser = load_default_japanese_parser();
+        let sentence = "すごーーーい。。。本当にーーー！！";
+        assert_eq!(
+            parser.boundary_scores(sentence),
+            allocating_scores(&parser, sentence)
+        );
+        assert_eq!(parser.parse("ーーー"), vec!["ーーー"]);
+        assert_eq!(parser.parse(LONG_SENTENCE).concat(), LONG_SENTENCE);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_load_parser_from_file_verbose() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/src/models/ja.json");
+        let (parser, report) = load_parser_from_file_verbose(path).unwrap();
+        assert_eq!(report.file_size, std::fs::metadata(path).unwrap().len());
+        assert_eq!(report.stats, japanese_model().unwrap().stats());
+        assert_eq!(
+            report.stats.entries_in(FeatureGroup::UW3),
+            japanese_model().unwrap().uw3.len()
+        );
+        assert!(report.stats.total() > 0);
+        assert_eq!(
+            parser.parse(LONG_SENTENCE),
+            load_default_japanese_parser().parse(LONG_SENTENCE)
+        );
+
+        assert!(load_parser_from_file_verbose("does/not/exist.json").is_err());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_chunk_ngrams() {
+        let parser = load_default_japanese_parser();
+        let sentence = "今日は良い天気ですね。";
+        assert_eq!(
+            parser.chunk_ngrams(sentence, 2),
+            vec!["今日は良い", "良い天気ですね。"]
+        );
+        assert_eq!(parser.chunk_ngrams(sentence, 1), parser.parse(sentence));
+        assert_eq!(parser.chunk_ngrams(sentence, 5), vec![sentence]);
+        assert!(parser.chunk_ngrams(sentence, 0).is_empty());
+        assert!(parser.chunk_ngrams("", 2).is_empty());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_field_separators() {
+        let parser = load_default_japanese_parser().with_field_separators(&['\t']);
+        assert_eq!(
+            parser.parse("a\t今日は天気\tb"),
+            vec!["a", "\t", "今日は", "天気", "\t", "b"]
+        );
+        assert_eq!(parser.parse("\t\t"), vec!["\t", "\t"]);
+        assert!(parser.has_break("今\t"));
+        assert_eq!(parser.longest_chunk("今日\t").as_deref(), Some("今日"));
+        assert_eq!(
+            parser.parse_limited("a\t今日は天気\tb", 2),
+            vec!["a", "\t今日は天気\tb"]
+        );
+
+        let sentence = "今日は天気です。\t明日は雨です。";
+        let (chunks, scores) = parser.parse_with_scores(sentence);
+        assert_eq!(scores.len(), sentence.chars().count() - 1);
+        assert_eq!(scores[7], f64::INFINITY);
+        assert_eq!(scores[8], f64::INFINITY);
+        let unsplit = load_default_japanese_parser();
+        assert_eq!(chunks[..2], unsplit.parse("今日は天気です。")[..]);
+        assert_eq!(chunks[3..], unsplit.parse("明日は雨です。")[..]);
+        assert_eq!(parser.parse_ranges(sentence).len(), chunks.len());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_from_loader() {
+        let parser = Parser::from_loader(|| Ok(Model::empty())).unwrap();
+        assert_eq!(parser.parse("今日は"), vec!["今日は"]);
+
+        let result =
+            Parser::from_loader(|| Err(BudouXError::ModelLoadError("cache miss".to_string())));
+        assert!(matches!(result, Err(BudouXError::ModelLoadError(msg)) if msg == "cache miss"));
+
+        let parser = Parser::from_loader(|| Ok(japanese_model().unwrap().clone())).unwrap();
+        assert_eq!(
+            parser.parse("今日は天気です。"),
+            vec!["今日は", "天気です。"]
+        );
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_reversed() {
+        let parser = load_default_japanese_parser();
+        let mut expected = parser.parse(LONG_SENTENCE);
+        expected.reverse();
+        assert_eq!(parser.parse_reversed(LONG_SENTENCE), expected);
+        assert_eq!(
+            parser.parse_reversed("今日は天気です。"),
+            vec!["天気です。", "今日は"]
+        );
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_explain_boundary() {
+        let parser = load_default_japanese_parser();
+        let sentence = "今日は天気です。";
+        let scores = parser.boundary_scores(sentence);
+        for i in 1..sentence.chars().count() {
+            let explanation = parser.explain_boundary(sentence, i).unwrap();
+            assert_eq!(explanation.score, scores[i - 1]);
+            assert_eq!(explanation.is_break, parser.break_mask(sentence)[i]);
+        }
+
+        let explanation = parser.explain_boundary(sentence, 1).unwrap();
+        let groups: Vec<FeatureGroup> = explanation
+            .contributions
+            .iter()
+            .map(|(group, _, _)| *group)
+            .collect();
+        assert!(!groups.contains(&FeatureGroup::UW1));
+        assert!(groups.contains(&FeatureGroup::TW4));
+        assert!(explanation.contributions.contains(&(
+            FeatureGroup::BW2,
+            "今日".to_string(),
+            parser
+                .model
+                .feature_score(FeatureGroup::BW2, "今日")
+                .map_or(0.0, f64::from)
+        )));
+
+        assert!(parser.explain_boundary(sentence, 0).is_none());
+        assert!(parser.explain_boundary(sentence, 8).is_none());
+
+        let parser = load_default_japanese_parser().with_strip_bom(true);
+        let sentence = "\u{FEFF}今日は天気です。";
+        let scores = parser.boundary_scores(sentence);
+        let mask = parser.break_mask(sentence);
+        assert!(parser.explain_boundary(sentence, 1).is_none());
+        for i in 2..sentence.chars().count() {
+            let explanation = parser.explain_boundary(sentence, i).unwrap();
+            assert_eq!(explanation.score, scores[i - 2]);
+            assert_eq!(explanation.is_break, mask[i]);
+        }
+
+        let parser = load_default_japanese_parser().with_field_separators(&['|']);
+        let sentence = "今日は|天気です。";
+        let scores = parser.boundary_scores(sentence);
+        let mask = parser.break_mask(sentence);
+        for i in 1..sentence.chars().count() {
+            let explanation = parser.explain_boundary(sentence, i).unwrap();
+            assert_eq!(explanation.score, scores[i - 1]);
+            assert_eq!(explanation.is_break, mask[i]);
+        }
+        assert!(parser
+            .explain_boundary(sentence, 3)
+            .unwrap()
+            .contributions
+            .is_empty());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_feature_group_importance() {
+        let parser = load_default_japanese_parser();
+        let importance = feature_group_importance(&parser, &["今日は天気です。", LONG_SENTENCE]);
+        assert_eq!(importance.len(), FeatureGroup::ALL.len());
+        let total: f64 = importance.values().sum();
+        assert!((total - 1.0).abs() < 1e-9);
+        assert!(importance.values().all(|&share| share >= 0.0));
+
+        let empty = feature_group_importance(&Parser::new(Model::empty()), &["今日は"]);
+        assert!(empty.values().all(|&share| share == 0.0));
+
+        // Matches the contributions `explain_boundary` reports
+        let parser = parser.with_field_separators(&['\t']).with_strip_bom(true);
+        let corpus = ["\u{FEFF}今日は\t天気です。", LONG_SENTENCE];
+        let mut totals: HashMap<FeatureGroup, f64> = HashMap::new();
+        for sentence in corpus {
+            for i in 1..sentence.chars().count() {
+                if let Some(explanation) = parser.explain_boundary(sentence, i) {
+                    for (group, _, score) in explanation.contributions {
+                        *totals.entry(group).or_default() += score.abs();
+                    }
+                }
+            }
+        }
+        let sum: f64 = FeatureGroup::ALL.iter().map(|group| totals[group]).sum();
+        let importance = feature_group_importance(&parser, &corpus);
+        for group in FeatureGroup::ALL {
+            assert_eq!(importance[&group], totals[&group] / sum);
+        }
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_model_extra_groups() {
+        let json = r#"{"UW3": {"。": 10}, "UW7": {"a": 1}}"#;
+        let model: Model = serde_json::from_str(json).unwrap();
+        assert_eq!(model.feature_score(FeatureGroup::UW3, "。"), Some(10));
+        assert!(model.tw4.is_empty());
+        assert_eq!(model.extra["UW7"]["a"], 1);
+        assert_eq!(model.extra.len(), 1);
+
+        let round_trip: Model =
+            serde_json::from_str(&serde_json::to_string(&model).unwrap()).unwrap();
+        assert_eq!(round_trip.extra, model.extra);
+        assert!(Parser::from_json_str(json).is_ok());
+        assert!(japanese_model().unwrap().extra.is_empty());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_parse_filtered() {
+        let parser = load_default_japanese_parser().with_always_break_after(&['、', '」']);
+        let sentence = "「はい」、そうです。";
+        let chunks = parser.parse(sentence);
+        assert!(chunks.contains(&"、".to_string()));
+
+        let is_punctuation = |chunk: &str| chunk.chars().all(|c| !c.is_alphanumeric());
+        let kept = parser.parse_filtered(sentence, |chunk| !is_punctuation(chunk));
+        let expected: Vec<String> = chunks.into_iter().filter(|c| !is_punctuation(c)).collect();
+        assert_eq!(kept, expected);
+        assert!(kept.iter().any(|chunk| chunk.contains("そう")));
+        assert!(parser.parse_filtered(sentence, |_| false).is_empty());
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_display() {
+        let parser = load_default_japanese_parser();
+        let entries = japanese_model().unwrap().stats().total();
+        assert_eq!(
+            parser.to_string(),
+            format!(
+                "Parser {{ language: ja, threshold: 0.0, entries: {} }}",
+                entries
+            )
+        );
+
+        let parser = parser
+            .with_threshold(-1.5)
+            .with_min_chunk_chars(2)
+            .with_strip_bom(true);
+        let summary = parser.to_string();
+        assert!(summary.contains("threshold: -1.5"));
+        assert!(summary.contains(&format!("entries: {}", entries)));
+        assert!(summary.contains("min_chunk_chars: 2"));
+        assert!(summary.contains("options: [strip_bom]"));
+        assert!(Parser::new(Model::empty())
+            .to_string()
+            .contains("entries: 0"));
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_break_before() {
+        let sentence = "彼は「はい」と言った";
+        let parser = load_default_japanese_parser().with_threshold(10000.0);
+        assert_eq!(parser.parse(sentence), vec![sentence]);
+
+        let parser = parser.with_break_before(&['「']);
+        assert_eq!(parser.parse(sentence), vec!["彼は", "「はい」と言った"]);
+        assert_eq!(parser.parse("「はい」"), vec!["「はい」"]);
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_annotate() {
+        let parser = load_default_japanese_parser();
+        assert_eq!(
+            parser.annotate("今日は天気です。明日は雨です。", "|", "\n"),
+            "今日は|天気です。\n明日は|雨です。"
+        );
+        assert_eq!(parser.annotate("今日は", "|", "\n"), "今日は");
+        assert_eq!(parser.annotate("", "|", "\n"), "");
+    }
+
+    #[cfg(feature = "model-ja")]
+    #[test]
+    fn test_sorted_tables_match_hashed() {
+        let mut model = Model::empty();
+        for group in FeatureGroup::ALL {
+            let mut entries: Vec<(&String, &i32)> = models()["ja"].feature(group).iter().collect();
+            entries.sort_by_key(|(_, score)| -score.abs());
+            model.feature_mut(group).extend(
+                entries
+                    .into_iter()
+                    .take(SORTED_FEATURE_LIMIT)
+                    .map(|(key, &score)| (key.clone(), score)),
+            );
+        }
+        let mut sorted = Parser::new(model);
+        assert!(sorted
+            .tables
+            .iter()
+            .all(|table| matches!(table, FeatureTable::Sorted(_))));
+
+        let mut hashed = sorted.clone();
+        hashed.tables = FeatureGroup::ALL.map(|_| FeatureTable::Hashed);
+        assert_eq!(
+            sorted.boundary_scores(LONG_SENTENCE),
+            hashed.boundary_scores(LONG_SENTENCE)
+        );
+        assert_eq!(sorted.parse(LONG_SENTENCE), hashed.parse(LONG_SENTENCE));
+
+        sorted.set_feature_score(FeatureGroup::BW2, "は天", 5000);
+        hashed.set_feature_score(FeatureGroup::BW2, "は天", 5000);
+        hashed.tables = FeatureGroup::ALL.map(|_| FeatureTable::Hashed);
+        assert_eq!(
+            sorted.boundary_scores(LONG_SENTENCE),
+            hashed.boundary_scores(LONG_SENTENCE)
+        );
+    }
+
+    #[cfg(feature = "futures")]
+    #[tokio::test]
+    async fn test_segment_stream_late_context() {
+        use futures_util::{stream, TryStreamExt};
+
+        for (parser, sentence) in late_context_cases() {
+            let pieces: Vec<String> = sentence.chars().map(String::from).collect();
+            let input = stream::iter(pieces.into_iter().map(Ok));
+            let chunks: Vec<String> = parser.segment_stream(input).try_collect().await.unwrap();
+            assert_eq!(chunks, parser.parse(sentence), "input: {}", sentence);
+        }
+    }
+
+    #[cfg(all(feature = "futures", feature = "model-ja"))]
+    #[tokio::test]
+    async fn test_segment_stream() {
+        use futures_util::{stream, StreamExt, TryStreamExt};
+
+        let chars: Vec<char> = LONG_SENTENCE.chars().collect();
+        let pieces: Vec<String> = chars
+            .chunks(5)
+            .flat_map(|piece| {
+                let (a, b) = piece.split_at(piece.len().min(2));
+                [a.iter().collect(), b.iter().collect()]
+            })
+            .collect();
+        for parser in [
+            load_default_japanese_parser(),
+            load_default_japanese_parser().with_min_chunk_chars(6),
+            load_default_japanese_parser().with_field_separators(&['、']),
+        ] {
+            let input = stream::iter(pieces.clone().into_iter().map(Ok));
+            let chunks: Vec<String> = parser.segment_stream(input).try_collect().await.unwrap();
+            assert_eq!(chunks, parser.parse(LONG_SENTENCE));
+        }
+
+        let parser = load_default_japanese_parser();
+        let input = stream::iter(vec![
+            Ok("今日は天気です。".to_string()),
+            Err(std::io::Error::other("closed")),
+            Ok("明日".to_string()),
+        ]);
+        let items: Vec<_> = parser.segment_stream(input).collect().await;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_ref().unwrap(), "今日は");
+        assert!(items[1].is_err());
     }
 }
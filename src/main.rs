@@ -13,27 +13,168 @@ struct Cli {
     #[arg(required = true)]
     text: String,
 
-    /// Output format (text or json)
+    /// Output format (text, json, json-rich or json-info)
     #[arg(short, long, default_value = "text")]
     format: String,
+
+    /// Output the text as HTML with break opportunities (zwsp or wbr)
+    #[arg(long, value_parser = ["zwsp", "wbr"])]
+    html: Option<String>,
+
+    /// Load the model from a JSON file instead of the embedded one (twice
+    /// with --diff)
+    #[arg(long, value_name = "PATH")]
+    model: Vec<String>,
+
+    /// Use the bundled model of a language code, or detect it from the text
+    /// with `auto`, falling back to Japanese; --model takes precedence
+    #[arg(long, value_name = "CODE")]
+    lang: Option<String>,
+
+    /// Print model load and parse durations to stderr
+    #[arg(long)]
+    time: bool,
+
+    /// List the breaks where the first --model and the second one, or the
+    /// embedded model, disagree
+    #[arg(long, conflicts_with_all = ["format", "html", "lang"])]
+    diff: bool,
+
+    /// Print the --diff output without ANSI colors
+    #[arg(long)]
+    no_color: bool,
+}
+
+/// Print an error and exit with status 1
+#[cfg(feature = "cli")]
+fn fail(message: String) -> ! {
+    eprintln!("budoux: {}", message);
+    std::process::exit(1);
+}
+
+/// Load a parser from a model file, exiting on error
+#[cfg(feature = "cli")]
+fn load_model(path: &str) -> budoux_rust_wrapper::Parser {
+    budoux_rust_wrapper::load_parser_from_file(path)
+        .unwrap_or_else(|e| fail(format!("cannot use model {}: {}", path, e)))
+}
+
+/// List each break where the two parsers disagree, one per line
+///
+/// Lines start with `-` for a break only the first parser makes and `+` for
+/// one only the second makes, followed by the char offset and the text with
+/// `|` at the break. Colors follow the usual diff red and green.
+#[cfg(feature = "cli")]
+fn render_diff(
+    cli: &Cli,
+    first: &budoux_rust_wrapper::Parser,
+    second: &budoux_rust_wrapper::Parser,
+) -> String {
+    let a = first.parse(&cli.text);
+    let b = second.parse(&cli.text);
+    let mut output = String::new();
+    for (offset, in_first) in budoux_rust_wrapper::diff_segmentation(&a, &b) {
+        let (sign, color) = if in_first { ('-', 31) } else { ('+', 32) };
+        let split = cli
+            .text
+            .char_indices()
+            .nth(offset)
+            .map_or(cli.text.len(), |(i, _)| i);
+        let line = format!(
+            "{}{}\t{}|{}",
+            sign,
+            offset,
+            &cli.text[..split],
+            &cli.text[split..]
+        );
+        if cli.no_color {
+            output += &format!("{}\n", line);
+        } else {
+            output += &format!("\x1b[{}m{}\x1b[0m\n", color, line);
+        }
+    }
+    output
+}
+
+/// Produce the output for the requested mode, including the final newline
+#[cfg(feature = "cli")]
+fn render(cli: &Cli, parser: &budoux_rust_wrapper::Parser) -> String {
+    if let Some(mode) = cli.html.as_deref() {
+        let html = match mode {
+            "wbr" => parser.to_wbr_html(&cli.text),
+            _ => parser.parse_joined(&cli.text, budoux_rust_wrapper::ZWSP),
+        };
+        return html + "\n";
+    }
+
+    match cli.format.as_str() {
+        "json-rich" => {
+            let result = parser.segment_result(&cli.text);
+            serde_json::to_string_pretty(&result).unwrap() + "\n"
+        }
+        "json-info" => serde_json::to_string_pretty(&parser.parse_info(&cli.text)).unwrap() + "\n",
+        "json" => serde_json::to_string_pretty(&parser.parse(&cli.text)).unwrap() + "\n",
+        _ => parser
+            .parse(&cli.text)
+            .into_iter()
+            .map(|chunk| chunk + "\n")
+            .collect(),
+    }
 }
 
 fn main() {
     #[cfg(feature = "cli")]
     {
         let cli = Cli::parse();
-        let parser = budoux_rust_wrapper::load_default_japanese_parser();
-        let result = parser.parse(&cli.text);
-
-        match cli.format.as_str() {
-            "json" => {
-                println!("{}", serde_json::to_string_pretty(&result).unwrap());
-            }
-            _ => {
-                for chunk in result {
-                    println!("{}", chunk);
+
+        if cli.diff {
+            let (first, second) = match cli.model.as_slice() {
+                [path] => (
+                    load_model(path),
+                    budoux_rust_wrapper::load_default_japanese_parser(),
+                ),
+                [first, second] => (load_model(first), load_model(second)),
+                _ => fail("--diff needs one or two --model paths".to_string()),
+            };
+            print!("{}", render_diff(&cli, &first, &second));
+            return;
+        }
+
+        let start = std::time::Instant::now();
+        let parser = match cli.model.as_slice() {
+            [path] => load_model(path),
+            [] => match cli.lang.as_deref() {
+                Some("auto") => {
+                    let detected = budoux_rust_wrapper::detect_language(&cli.text);
+                    match detected.map(|language| language.code()) {
+                        Some(code) if budoux_rust_wrapper::models().contains_key(code) => {
+                            budoux_rust_wrapper::load_parser_for(code).unwrap_or_else(|e| {
+                                fail(format!("cannot use language {}: {}", code, e))
+                            })
+                        }
+                        detected => {
+                            if let Some(code) = detected {
+                                eprintln!("budoux: no bundled model for {}, using ja", code);
+                            }
+                            budoux_rust_wrapper::load_default_japanese_parser()
+                        }
+                    }
                 }
-            }
+                Some(code) => budoux_rust_wrapper::load_parser_for(code)
+                    .unwrap_or_else(|e| fail(format!("cannot use language {}: {}", code, e))),
+                None => budoux_rust_wrapper::load_default_japanese_parser(),
+            },
+            _ => fail("--model can only be repeated with --diff".to_string()),
+        };
+        let load_time = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let output = render(&cli, &parser);
+        let parse_time = start.elapsed();
+
+        print!("{}", output);
+        if cli.time {
+            eprintln!("time: load {:?}, parse {:?}", load_time, parse_time);
         }
     }
 
@@ -0,0 +1,143 @@
+//! Integration tests for the BudouX CLI
+
+#![cfg(all(feature = "cli", feature = "model-ja"))]
+
+use std::process::Command;
+
+fn budoux(args: &[&str]) -> String {
+    budoux_with_stderr(args).0
+}
+
+fn budoux_with_stderr(args: &[&str]) -> (String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_budoux"))
+        .args(args)
+        .output()
+        .expect("failed to run budoux");
+    assert!(output.status.success());
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_html_zwsp() {
+    let stdout = budoux(&["--html", "zwsp", "今日は天気です。"]);
+    assert_eq!(stdout, "今日は\u{200B}天気です。\n");
+}
+
+#[test]
+fn test_html_wbr() {
+    let stdout = budoux(&["--html", "wbr", "今日は<b>天気</b>です。"]);
+    assert!(stdout.contains("<wbr>"));
+    assert!(stdout.contains("&lt;b&gt;"));
+    assert!(!stdout.contains("<b>"));
+}
+
+#[test]
+fn test_format_json_rich() {
+    let stdout = budoux(&["--format", "json-rich", "今日は天気です。"]);
+    let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(json["chunks"], serde_json::json!(["今日は", "天気です。"]));
+    assert_eq!(json["count"], 2);
+    assert_eq!(json["char_len"], 8);
+    assert_eq!(json["lang"], "ja");
+}
+
+#[test]
+fn test_format_json_info() {
+    let stdout = budoux(&["--format", "json-info", "今日は天気です。"]);
+    let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(json[1]["text"], "天気です。");
+    assert_eq!(json[1]["char_start"], 3);
+    assert_eq!(json[1]["byte_start"], 9);
+}
+
+#[test]
+fn test_text_output() {
+    assert_eq!(budoux(&["今日は天気です。"]), "今日は\n天気です。\n");
+}
+
+#[test]
+fn test_time() {
+    let (stdout, stderr) = budoux_with_stderr(&["--time", "今日は天気です。"]);
+    assert_eq!(stdout, "今日は\n天気です。\n");
+    let line = stderr
+        .lines()
+        .find(|line| line.starts_with("time:"))
+        .unwrap();
+    assert!(line.contains("load"));
+    assert!(line.contains("parse"));
+
+    let (_, stderr) = budoux_with_stderr(&["今日は天気です。"]);
+    assert!(stderr.is_empty());
+}
+
+#[test]
+fn test_model_file() {
+    let path = std::env::temp_dir().join(format!("budoux-cli-{}.json", std::process::id()));
+    std::fs::write(&path, r#"{"UW4": {"気": 10000}}"#).unwrap();
+    let stdout = budoux(&["--model", path.to_str().unwrap(), "今日は天気です。"]);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(stdout, "今日は天\n気です。\n");
+}
+
+#[test]
+fn test_model_file_missing() {
+    let output = Command::new(env!("CARGO_BIN_EXE_budoux"))
+        .args(["--model", "/nonexistent/model.json", "今日は天気です。"])
+        .output()
+        .expect("failed to run budoux");
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("/nonexistent/model.json"));
+}
+
+#[test]
+fn test_lang_auto() {
+    let stdout = budoux(&["--lang", "auto", "今日は天気です。"]);
+    assert_eq!(stdout, "今日は\n天気です。\n");
+
+    // Han-only text is detected as Chinese, which has no bundled model
+    let (stdout, stderr) = budoux_with_stderr(&["--lang", "auto", "東京都庁"]);
+    assert_eq!(stdout, budoux(&["東京都庁"]));
+    assert!(stderr.contains("no bundled model for zh-hans, using ja"));
+
+    let (stdout, stderr) = budoux_with_stderr(&["--lang", "auto", "วันนี้อากาศดีมาก"]);
+    assert!(!stdout.is_empty());
+    assert!(stderr.contains("no bundled model for th"));
+}
+
+#[test]
+fn test_lang_unsupported() {
+    let output = Command::new(env!("CARGO_BIN_EXE_budoux"))
+        .args(["--lang", "th", "วันนี้อากาศดีมาก"])
+        .output()
+        .expect("failed to run budoux");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("cannot use language th"));
+}
+
+#[test]
+fn test_model_overrides_lang() {
+    let path = std::env::temp_dir().join(format!("budoux-cli-lang-{}.json", std::process::id()));
+    std::fs::write(&path, r#"{"UW4": {"気": 10000}}"#).unwrap();
+    let path = path.to_str().unwrap();
+    let stdout = budoux(&["--model", path, "--lang", "ja", "今日は天気です。"]);
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(stdout, "今日は天\n気です。\n");
+}
+
+#[test]
+fn test_diff() {
+    let path = std::env::temp_dir().join(format!("budoux-cli-diff-{}.json", std::process::id()));
+    std::fs::write(&path, r#"{"UW4": {"気": 10000}}"#).unwrap();
+    let path = path.to_str().unwrap();
+    let stdout = budoux(&["--diff", "--no-color", "--model", path, "今日は天気です。"]);
+    let colored = budoux(&["--diff", "--model", path, "今日は天気です。"]);
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(stdout, "+3\t今日は|天気です。\n-4\t今日は天|気です。\n");
+    assert!(colored.contains("\x1b[32m+3\t"));
+}
@@ -0,0 +1,139 @@
+"""Regenerate ja_cases.json from the official BudouX Python package.
+
+Usage: pip install budoux && python3 tests/data/capture_ja_cases.py
+
+Without the package installed, the cases are produced by `reference_parse`,
+a line-by-line transcription of upstream `budoux.Parser.parse`, run on the
+model bundled with this crate. The output records which one was used so the
+file can be refreshed once the package is available.
+"""
+
+import json
+import os
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+
+INPUTS = [
+    # 1-, 2- and 3-character inputs, where most feature windows fall off
+    # the edges
+    "今",
+    "。",
+    "a",
+    "今日",
+    "はい",
+    "私は",
+    "今日は",
+    "天気。",
+    "ありがとう",
+    # Sentences
+    "今日は天気です。",
+    "今日は良い天気ですね。",
+    "私はその人を常に先生と呼んでいた。",
+    "だから此処でもただ先生と書くだけで本名は打ち明けない。",
+    "私は遅刻魔で、待ち合わせにいつも遅刻してしまいます。",
+    "メールで待ち合わせ相手に一言、「ごめんね」と謝ればどうにかなると思っていました。",
+    "海外ではケータイを持っていない。",
+    "雨が降っているので、傘を持って行きましょう。",
+    "東京都庁は新宿にあります。",
+    "吾輩は猫である。名前はまだ無い。",
+    "どこで生れたかとんと見当がつかぬ。",
+    "明日の会議は午後三時から始まる予定です。",
+    "この本はとても面白かったので、友達にも勧めました。",
+    "駅から歩いて五分ほどのところに新しいカフェができた。",
+    "週末は家族と一緒に山へハイキングに行きました。",
+    # Punctuation and brackets
+    "「こんにちは」と彼は言った。",
+    "えっ！本当ですか？",
+    "はい、そうです。いいえ、違います。",
+    "（注）詳しくは別紙を参照してください。",
+    "『吾輩は猫である』は夏目漱石の小説です。",
+    "・・・と思ったが、やめた。",
+    # Latin and digit mixes
+    "BudouXは機械学習を使った改行整形ツールです。",
+    "iPhone15を買いました。",
+    "Rustで書かれたライブラリを使う。",
+    "2024年1月1日は月曜日でした。",
+    "価格は1,980円（税込）です。",
+    "詳細はhttps://example.comをご覧ください。",
+    "Hello, world!",
+    "JavaScriptとPythonの両方に対応しています。",
+    "ＡＢＣ株式会社の新製品が発表された。",
+    # Katakana-heavy and kana-only text
+    "コンピューターサイエンスの基礎を学ぶ。",
+    "ひらがなだけでかかれたぶんしょうです。",
+    "カタカナノミデカカレタブンショウデス。",
+    # Whitespace
+    "今日は 天気です。",
+    "今日は\n天気です。",
+]
+
+
+def reference_parse(model, sentence):
+    """Transcription of `budoux.Parser.parse` from the official package."""
+    if sentence == "":
+        return []
+    chunks = [sentence[0]]
+    base_score = -sum(sum(g.values()) for g in model.values()) * 0.5
+    for i in range(1, len(sentence)):
+        score = base_score
+        if i > 2:
+            score += model.get("UW1", {}).get(sentence[i - 3], 0)
+        if i > 1:
+            score += model.get("UW2", {}).get(sentence[i - 2], 0)
+        score += model.get("UW3", {}).get(sentence[i - 1], 0)
+        score += model.get("UW4", {}).get(sentence[i], 0)
+        if i + 1 < len(sentence):
+            score += model.get("UW5", {}).get(sentence[i + 1], 0)
+        if i + 2 < len(sentence):
+            score += model.get("UW6", {}).get(sentence[i + 2], 0)
+        if i > 1:
+            score += model.get("BW1", {}).get(sentence[i - 2:i], 0)
+        score += model.get("BW2", {}).get(sentence[i - 1:i + 1], 0)
+        if i + 1 < len(sentence):
+            score += model.get("BW3", {}).get(sentence[i:i + 2], 0)
+        if i > 2:
+            score += model.get("TW1", {}).get(sentence[i - 3:i], 0)
+        if i > 1:
+            score += model.get("TW2", {}).get(sentence[i - 2:i + 1], 0)
+        if i + 1 < len(sentence):
+            score += model.get("TW3", {}).get(sentence[i - 1:i + 2], 0)
+        if i + 2 < len(sentence):
+            score += model.get("TW4", {}).get(sentence[i:i + 3], 0)
+        if score > 0:
+            chunks.append(sentence[i])
+        else:
+            chunks[-1] += sentence[i]
+    return chunks
+
+
+def main():
+    try:
+        import budoux
+        from importlib.metadata import version
+
+        parse = budoux.load_default_japanese_parser().parse
+        header = {
+            "source": "budoux.load_default_japanese_parser().parse from the "
+            "official Python package",
+            "budoux_version": version("budoux"),
+        }
+    except ImportError:
+        model_path = os.path.join(HERE, "..", "..", "src", "models", "ja.json")
+        with open(model_path, encoding="utf-8") as f:
+            model = json.load(f)
+        parse = lambda sentence: reference_parse(model, sentence)
+        header = {
+            "source": "reference_parse in capture_ja_cases.py, a transcription "
+            "of upstream budoux.Parser.parse, on src/models/ja.json; rerun "
+            "with the budoux package installed to capture from upstream",
+            "budoux_version": None,
+        }
+
+    cases = [{"input": s, "expected": parse(s)} for s in INPUTS]
+    with open(os.path.join(HERE, "ja_cases.json"), "w", encoding="utf-8") as f:
+        json.dump(dict(header, cases=cases), f, ensure_ascii=False, indent=2)
+        f.write("\n")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,40 @@
+//! Checks the trace entries emitted for each boundary decision
+
+#![cfg(all(feature = "log", feature = "model-ja"))]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use budoux_rust_wrapper::load_default_japanese_parser;
+use log::{Level, LevelFilter, Metadata, Record};
+
+/// Logger counting the trace entries from this crate
+struct CountingLogger;
+
+static ENTRIES: AtomicUsize = AtomicUsize::new(0);
+
+impl log::Log for CountingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() == Level::Trace && metadata.target().starts_with("budoux_rust_wrapper")
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            ENTRIES.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CountingLogger = CountingLogger;
+
+#[test]
+fn test_trace_entry_per_boundary() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(LevelFilter::Trace);
+
+    let sentence = "今日は天気です。";
+    let parser = load_default_japanese_parser();
+    assert_eq!(parser.parse(sentence), vec!["今日は", "天気です。"]);
+    assert_eq!(ENTRIES.load(Ordering::SeqCst), sentence.chars().count() - 1);
+}
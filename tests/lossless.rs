@@ -0,0 +1,52 @@
+//! Property tests checking that segmentation never loses or reorders text
+
+#![cfg(feature = "model-ja")]
+
+use budoux_rust_wrapper::load_default_japanese_parser;
+use proptest::prelude::*;
+
+/// Characters from the scripts the parser is most likely to meet
+fn any_char() -> impl Strategy<Value = char> {
+    prop_oneof![
+        prop::char::range('\u{3041}', '\u{3096}'),
+        prop::char::range('\u{30A1}', '\u{30FA}'),
+        prop::char::range('\u{4E00}', '\u{9FFF}'),
+        prop::char::range(' ', '~'),
+        prop::char::range('\u{1F300}', '\u{1F64F}'),
+        prop::char::range('\u{0300}', '\u{036F}'),
+        prop::sample::select(vec![
+            '、', '。', '「', '」', '\u{3000}', '\u{FEFF}', '\u{200D}'
+        ]),
+        any::<char>(),
+    ]
+}
+
+fn any_text() -> impl Strategy<Value = String> {
+    prop::collection::vec(any_char(), 0..48).prop_map(|chars| chars.into_iter().collect())
+}
+
+proptest! {
+    #[test]
+    fn test_parse_concat_is_input(text in any_text()) {
+        let parser = load_default_japanese_parser();
+        let chunks = parser.parse(&text);
+        prop_assert_eq!(chunks.concat(), text.clone());
+        prop_assert!(chunks.iter().all(|chunk| !chunk.is_empty()));
+    }
+
+    #[test]
+    fn test_parse_ranges_match_chunks(text in any_text()) {
+        let parser = load_default_japanese_parser().with_halfwidth_kana_fold(true);
+        let chunks = parser.parse(&text);
+        let ranges = parser.parse_ranges(&text);
+        let sliced: Vec<&str> = ranges.iter().map(|range| &text[range.clone()]).collect();
+        prop_assert_eq!(sliced, chunks);
+    }
+
+    #[test]
+    fn test_parse_lossy_any_bytes(bytes in prop::collection::vec(any::<u8>(), 0..96)) {
+        let parser = load_default_japanese_parser();
+        let chunks = parser.parse_lossy(&bytes);
+        prop_assert_eq!(chunks.concat(), String::from_utf8_lossy(&bytes));
+    }
+}
@@ -0,0 +1,20 @@
+//! Checks the embedded Japanese model on its own
+//!
+//! Run with `cargo test --no-default-features --features model-ja` to make
+//! sure the model works without any other optional feature.
+
+#![cfg(feature = "model-ja")]
+
+use budoux_rust_wrapper::{load_default_japanese_parser, models, try_load_default_japanese_parser};
+
+#[test]
+fn test_load_default_japanese_parser() {
+    let parser = load_default_japanese_parser();
+    assert_eq!(
+        parser.parse("今日は天気です。"),
+        vec!["今日は", "天気です。"]
+    );
+    assert_eq!(parser.language(), Some("ja"));
+    assert!(try_load_default_japanese_parser().is_ok());
+    assert!(models().contains_key("ja"));
+}
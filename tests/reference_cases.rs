@@ -0,0 +1,71 @@
+//! Compares segmentation with a reference transcription of the algorithm
+//!
+//! Each case in `tests/data/ja_cases.json` pairs an input with the chunks
+//! returned by `reference_parse` in `tests/data/capture_ja_cases.py`, a
+//! Python transcription of `budoux.Parser.parse` run on the bundled model.
+//! The cases were not captured from the official package (the file records
+//! `"budoux_version": null`), so they catch regressions and disagreements
+//! with that transcription, not differences from upstream. Running the script
+//! with the `budoux` package installed replaces them with upstream output.
+//! New cases go in the script's `INPUTS` list.
+
+#![cfg(feature = "model-ja")]
+
+use budoux_rust_wrapper::load_default_japanese_parser;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Cases {
+    cases: Vec<Case>,
+}
+
+#[derive(Deserialize)]
+struct Case {
+    input: String,
+    expected: Vec<String>,
+}
+
+fn load_cases() -> Vec<Case> {
+    let cases: Cases = serde_json::from_str(include_str!("data/ja_cases.json")).unwrap();
+    cases.cases
+}
+
+#[test]
+fn test_matches_reference_ja() {
+    let cases = load_cases();
+    assert!(!cases.is_empty());
+
+    let parser = load_default_japanese_parser();
+    for case in cases {
+        assert_eq!(
+            parser.parse(&case.input),
+            case.expected,
+            "input: {}",
+            case.input
+        );
+    }
+}
+
+// Feature windows that reach past the input edges are skipped, not padded;
+// on inputs this short most windows do, so these pin that behavior against
+// the transcription rather than upstream
+#[test]
+fn test_short_inputs_match_reference_ja() {
+    let short: Vec<Case> = load_cases()
+        .into_iter()
+        .filter(|case| case.input.chars().count() <= 3)
+        .collect();
+    for len in 1..=3 {
+        assert!(short.iter().any(|case| case.input.chars().count() == len));
+    }
+
+    let parser = load_default_japanese_parser();
+    for case in short {
+        assert_eq!(
+            parser.parse(&case.input),
+            case.expected,
+            "input: {}",
+            case.input
+        );
+    }
+}
@@ -0,0 +1,76 @@
+//! Checks the tracing spans emitted around parsing
+
+#![cfg(all(feature = "tracing", feature = "model-ja"))]
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use budoux_rust_wrapper::load_default_japanese_parser;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::Layer;
+
+/// Name and fields of a recorded span
+type RecordedSpan = (&'static str, HashMap<&'static str, String>);
+
+/// Layer keeping the name and fields of every span, in creation order
+#[derive(Clone, Default)]
+struct SpanRecorder {
+    spans: Arc<Mutex<Vec<RecordedSpan>>>,
+    // Index in `spans` of each open span, as span ids are reused
+    open: Arc<Mutex<HashMap<u64, usize>>>,
+}
+
+struct FieldVisitor<'a>(&'a mut HashMap<&'static str, String>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name(), format!("{:?}", value));
+    }
+}
+
+impl<S: Subscriber> Layer<S> for SpanRecorder {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, _ctx: Context<'_, S>) {
+        let mut fields = HashMap::new();
+        attrs.record(&mut FieldVisitor(&mut fields));
+        let mut spans = self.spans.lock().unwrap();
+        self.open.lock().unwrap().insert(id.into_u64(), spans.len());
+        spans.push((attrs.metadata().name(), fields));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, _ctx: Context<'_, S>) {
+        let idx = self.open.lock().unwrap()[&id.into_u64()];
+        let (_, fields) = &mut self.spans.lock().unwrap()[idx];
+        values.record(&mut FieldVisitor(fields));
+    }
+
+    fn on_close(&self, id: Id, _ctx: Context<'_, S>) {
+        self.open.lock().unwrap().remove(&id.into_u64());
+    }
+}
+
+#[test]
+fn test_parse_spans() {
+    let recorder = SpanRecorder::default();
+    let subscriber = tracing_subscriber::registry().with(recorder.clone());
+    let parser = load_default_japanese_parser();
+    let sentence = "今日は天気です。";
+    tracing::subscriber::with_default(subscriber, || {
+        assert_eq!(parser.parse(sentence), vec!["今日は", "天気です。"]);
+        parser.parse_all(&[sentence, "私の家"]);
+    });
+
+    let spans = recorder.spans.lock().unwrap();
+    let names: Vec<&str> = spans.iter().map(|(name, _)| *name).collect();
+    assert_eq!(names, vec!["parse", "parse_all", "parse", "parse"]);
+
+    let (_, fields) = &spans[0];
+    assert_eq!(fields["input_len"], sentence.len().to_string());
+    assert_eq!(fields["chunks"], "2");
+
+    let (_, fields) = &spans[1];
+    assert_eq!(fields["sentences"], "2");
+    assert_eq!(fields["chunks"], "4");
+}